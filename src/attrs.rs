@@ -0,0 +1,244 @@
+use std::ffi;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs as unix_fs;
+use std::os::unix::fs::MetadataExt;
+use std::path;
+use std::ptr;
+
+use crate::jobs;
+
+// POSIX ACLs are stored as extended attributes under these names.
+const ACL_ACCESS: &[u8] = b"system.posix_acl_access";
+const ACL_DEFAULT: &[u8] = b"system.posix_acl_default";
+
+// An attribute that couldn't be carried over to a copy.
+#[derive(Debug)]
+pub struct Unpreserved {
+    pub attribute: String,
+    pub error: io::Error,
+}
+
+// Gives `dst` the attributes of `src` (described by `metadata`) that `preserve`
+// asks for. Symlinks themselves are changed, not what they point to. Whatever
+// could not be preserved is returned; the copy stands either way.
+pub fn preserve(
+    src: &path::Path,
+    dst: &path::Path,
+    metadata: &fs::Metadata,
+    preserve: jobs::Preserve,
+) -> Vec<Unpreserved> {
+    let mut failures = vec![];
+    let mut fail = |attribute: &str, error: io::Error| {
+        failures.push(Unpreserved {
+            attribute: attribute.to_string(),
+            error,
+        })
+    };
+
+    // Changing the owner clears the setuid and setgid bits, so it goes first.
+    if preserve.ownership
+        && let Err(err) = unix_fs::lchown(dst, Some(metadata.uid()), Some(metadata.gid()))
+    {
+        // Without privileges the group may still be one the user is in.
+        match unix_fs::lchown(dst, None, Some(metadata.gid())) {
+            Ok(()) => fail("owner", err),
+            Err(_) => fail("owner and group", err),
+        }
+    }
+
+    if preserve.mode
+        && !metadata.file_type().is_symlink()
+        && let Err(err) = fs::set_permissions(dst, metadata.permissions())
+    {
+        fail("mode", err);
+    }
+
+    // ACLs after the mode: setting the mode rewrites the ACL mask.
+    if preserve.xattrs || preserve.acls {
+        match list_xattrs(src) {
+            Ok(names) => {
+                for name in names {
+                    let is_acl = [ACL_ACCESS, ACL_DEFAULT].contains(&name.as_bytes());
+                    if (is_acl && !preserve.acls) || (!is_acl && !preserve.xattrs) {
+                        continue;
+                    }
+                    let copied =
+                        get_xattr(src, &name).and_then(|value| set_xattr(dst, &name, &value));
+                    if let Err(err) = copied {
+                        let attribute = if is_acl {
+                            String::from("ACL")
+                        } else {
+                            format!("xattr {}", name.to_string_lossy())
+                        };
+                        fail(&attribute, err);
+                    }
+                }
+            }
+            Err(err) => fail("xattrs", err),
+        }
+    }
+
+    // Last, as everything before may touch the copy's ctime and atime.
+    if preserve.timestamps
+        && let Err(err) = set_times(dst, metadata)
+    {
+        fail("timestamps", err);
+    }
+
+    failures
+}
+
+fn c_path(p: &path::Path) -> io::Result<ffi::CString> {
+    ffi::CString::new(p.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+// Whether the filesystem doesn't do extended attributes at all.
+fn unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENOTSUP)
+}
+
+fn list_xattrs(p: &path::Path) -> io::Result<Vec<ffi::CString>> {
+    let c_p = c_path(p)?;
+    loop {
+        let size = unsafe { libc::llistxattr(c_p.as_ptr(), ptr::null_mut(), 0) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            return if unsupported(&err) {
+                Ok(vec![])
+            } else {
+                Err(err)
+            };
+        }
+        let mut buf = vec![0u8; size as usize];
+        let n = unsafe { libc::llistxattr(c_p.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            // Grew since it was measured.
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(n as usize);
+        return Ok(buf
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| ffi::CString::new(name).ok())
+            .collect());
+    }
+}
+
+fn get_xattr(p: &path::Path, name: &ffi::CStr) -> io::Result<Vec<u8>> {
+    let c_p = c_path(p)?;
+    loop {
+        let size = unsafe { libc::lgetxattr(c_p.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; size as usize];
+        let n = unsafe {
+            libc::lgetxattr(
+                c_p.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        value.truncate(n as usize);
+        return Ok(value);
+    }
+}
+
+fn set_xattr(p: &path::Path, name: &ffi::CStr, value: &[u8]) -> io::Result<()> {
+    let c_p = c_path(p)?;
+    let result = unsafe {
+        libc::lsetxattr(
+            c_p.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_times(p: &path::Path, metadata: &fs::Metadata) -> io::Result<()> {
+    let c_p = c_path(p)?;
+    let times = [
+        libc::timespec {
+            tv_sec: metadata.atime(),
+            tv_nsec: metadata.atime_nsec(),
+        },
+        libc::timespec {
+            tv_sec: metadata.mtime(),
+            tv_nsec: metadata.mtime_nsec(),
+        },
+    ];
+    let result = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            c_p.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn copies_keep_mode_times_and_xattrs() {
+        let dir = env::temp_dir().join(format!("files_please-attrs-{}", uuidv7::create()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("src");
+        let dst = dir.join("dst");
+        fs::write(&src, b"data").unwrap();
+        fs::write(&dst, b"data").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o640)).unwrap();
+        let old = libc::timespec {
+            tv_sec: 1_000_000_000,
+            tv_nsec: 123_456_789,
+        };
+        let c_src = c_path(&src).unwrap();
+        unsafe { libc::utimensat(libc::AT_FDCWD, c_src.as_ptr(), [old, old].as_ptr(), 0) };
+        let name = ffi::CString::new("user.files_please").unwrap();
+        let has_xattrs = set_xattr(&src, &name, b"value").is_ok();
+
+        let metadata = fs::symlink_metadata(&src).unwrap();
+        let failures = preserve(&src, &dst, &metadata, jobs::Preserve::archive());
+        // Ownership of our own file can always be kept.
+        assert!(failures.is_empty(), "{:?}", failures);
+
+        let copied = fs::symlink_metadata(&dst).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(copied.mtime(), metadata.mtime());
+        assert_eq!(copied.mtime_nsec(), 123_456_789);
+        if has_xattrs {
+            assert_eq!(get_xattr(&dst, &name).unwrap(), b"value");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
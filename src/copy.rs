@@ -0,0 +1,1328 @@
+use std::cmp;
+use std::collections;
+use std::error;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs as unix_fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path;
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time;
+
+use crate::attrs;
+use crate::iobackend;
+use crate::jobs;
+use crate::runner;
+use crate::wal;
+
+const CHECKPOINT_INTERVAL: u64 = 8 * 1024 * 1024;
+// Files up to this size are copied in batches, without checkpoints.
+const SMALL_FILE_MAX: u64 = 256 * 1024;
+const SMALL_FILE_BATCH: usize = 64;
+const RETRY_BACKOFF: time::Duration = time::Duration::from_millis(200);
+const RETRY_BACKOFF_MAX: time::Duration = time::Duration::from_secs(10);
+
+// Failing to write the job log is never skipped or retried: carrying on would
+// do work that can't be accounted for after a crash.
+#[derive(Debug)]
+struct JobLogError(io::Error);
+
+impl fmt::Display for JobLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "job log: {}", self.0)
+    }
+}
+
+impl error::Error for JobLogError {}
+
+// An error the error policy has already dealt with, on its way out through the
+// guards of the directories above it. They pass it on as it is, so it is
+// retried and reported once, where it happened.
+#[derive(Debug)]
+struct Handled(io::Error);
+
+impl fmt::Display for Handled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for Handled {}
+
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+// Where copying `src` to `dst` lands: inside `dst` when it is an existing
+// directory, otherwise at `dst` itself.
+pub fn resolve_target(src: &path::Path, dst: &path::Path) -> path::PathBuf {
+    match src.file_name() {
+        Some(name) if dst.is_dir() => dst.join(name),
+        _ => dst.to_path_buf(),
+    }
+}
+
+// Makes `dst` another name for `first`, replacing whatever the collision
+// policy already agreed to overwrite.
+fn link(first: &path::Path, dst: &path::Path) -> io::Result<()> {
+    match fs::hard_link(first, dst) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            fs::remove_file(dst)?;
+            fs::hard_link(first, dst)
+        }
+        result => result,
+    }
+}
+
+// `mode` carries the file type: a FIFO, block or character device.
+fn mknod(p: &path::Path, mode: u32, rdev: u64) -> io::Result<()> {
+    let c_p = ffi::CString::new(p.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    if unsafe { libc::mknod(c_p.as_ptr(), mode, rdev) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub struct Copier<'c> {
+    job_id: &'c str,
+    job_log: &'c Mutex<wal::Wal>,
+    events: &'c mpsc::Sender<runner::Event>,
+    resume: &'c jobs::Resume,
+    error_policy: jobs::ErrorPolicy,
+    collision_policy: jobs::CollisionPolicy,
+    symlink_policy: jobs::SymlinkPolicy,
+    verify: bool,
+    preserve: jobs::Preserve,
+    backend: &'c mut dyn iobackend::Backend,
+    // How the current file is being copied.
+    strategy: iobackend::Strategy,
+    errors: Vec<jobs::FileError>,
+    verify_failures: usize,
+    // Where each file went once it was started, so retries and resumes write
+    // to the same place and a file is never taken for a collision with its own
+    // partial copy.
+    targets: collections::HashMap<path::PathBuf, path::PathBuf>,
+    kept: collections::HashSet<path::PathBuf>,
+    // The tree being copied, with symlinks above it resolved.
+    root: path::PathBuf,
+    // The first copy of every multiply linked source file by (device, inode),
+    // so the other names can be linked to it.
+    links: collections::HashMap<(u64, u64), path::PathBuf>,
+    files_done: u64,
+    bytes_done: u64,
+}
+
+impl<'c> Copier<'c> {
+    pub fn new(
+        job_id: &'c str,
+        job_log: &'c Mutex<wal::Wal>,
+        events: &'c mpsc::Sender<runner::Event>,
+        resume: &'c jobs::Resume,
+        options: jobs::TransferOptions,
+        backend: &'c mut dyn iobackend::Backend,
+    ) -> Copier<'c> {
+        Copier {
+            job_id,
+            job_log,
+            events,
+            resume,
+            error_policy: options.error_policy,
+            collision_policy: options.collision_policy,
+            symlink_policy: options.symlink_policy,
+            verify: options.verify,
+            preserve: options.preserve,
+            backend,
+            strategy: iobackend::Strategy::ReadWrite,
+            errors: resume.errors.clone(),
+            verify_failures: 0,
+            targets: resume.targets.clone(),
+            kept: resume.kept.clone(),
+            root: path::PathBuf::new(),
+            links: collections::HashMap::new(),
+            files_done: 0,
+            bytes_done: 0,
+        }
+    }
+
+    pub fn copy(&mut self, src: &path::Path, dst: &path::Path) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(src)?;
+        if metadata.is_dir() {
+            let src_abs = fs::canonicalize(src)?;
+            let dst_abs = path::absolute(dst)?;
+            if dst_abs.starts_with(&src_abs) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cannot copy {} into itself", src.display()),
+                ));
+            }
+        }
+
+        self.root = match (src.parent(), src.file_name()) {
+            (Some(parent), Some(name)) => fs::canonicalize(parent)?.join(name),
+            _ => fs::canonicalize(src)?,
+        };
+
+        let (files, bytes) = self.measure(src);
+        let _ = self.events.send(runner::Event::Totals {
+            job_id: self.job_id.to_string(),
+            files,
+            bytes,
+        });
+
+        self.guarded(src, |copier| copier.copy_entry(src, dst, &metadata))
+    }
+
+    // Fails if any copy didn't match its source. The mismatches are in the
+    // error list; everything else was copied and verified.
+    pub fn verified(&self) -> io::Result<()> {
+        if self.verify_failures == 0 {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} file(s) failed verification", self.verify_failures),
+        ))
+    }
+
+    // Files that were not copied, because of an error or a collision.
+    pub fn left_behind(&self) -> collections::HashSet<&path::Path> {
+        self.errors
+            .iter()
+            .map(|e| e.path.as_path())
+            .chain(self.kept.iter().map(|p| p.as_path()))
+            .collect()
+    }
+
+    // Settles where `src` goes when something other than a directory is at
+    // `dst`, the way copying it would, for a move that renames it there. None
+    // if it stays where it is.
+    pub fn resolve(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+    ) -> io::Result<Option<path::PathBuf>> {
+        let metadata = fs::symlink_metadata(src)?;
+        let Some(target) = self.resolve_collision(src, dst, &metadata)? else {
+            return Ok(None);
+        };
+        // Renaming a file over another name of itself leaves both in place.
+        if let Ok(target_metadata) = fs::symlink_metadata(&target)
+            && (target_metadata.dev(), target_metadata.ino()) == (metadata.dev(), metadata.ino())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} and {} are the same file",
+                    src.display(),
+                    target.display()
+                ),
+            ));
+        }
+        self.targets.insert(src.to_path_buf(), target.clone());
+        Ok(Some(target))
+    }
+
+    // Where `src` was copied to, if it was started.
+    pub fn target(&self, src: &path::Path) -> Option<&path::Path> {
+        self.targets.get(src).map(|p| p.as_path())
+    }
+
+    // Number of entries under `src` that are copied one by one, and the size
+    // of the regular files among them. Symlinks are followed where the copy
+    // follows them, so the count of files done never goes past it. Entries
+    // that can't be read are left out.
+    fn measure(&self, src: &path::Path) -> (u64, u64) {
+        let metadata = match fs::symlink_metadata(src) {
+            Ok(metadata) if metadata.is_symlink() && self.dereference(src).unwrap_or(false) => {
+                fs::metadata(src)
+            }
+            result => result,
+        };
+        let Ok(metadata) = metadata else {
+            return (0, 0);
+        };
+        if metadata.is_file() {
+            return (1, metadata.len());
+        }
+        if !metadata.is_dir() {
+            // Sockets are left behind; links, FIFOs and devices are made anew.
+            return (u64::from(!metadata.file_type().is_socket()), 0);
+        }
+        let Ok(entries) = fs::read_dir(src) else {
+            return (0, 0);
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| self.measure(&entry.path()))
+            .fold((0, 0), |(files, bytes), (f, b)| (files + f, bytes + b))
+    }
+
+    // Runs `op` for `src` under the job's error policy. Only errors from `op`
+    // itself are retried; those from guarded entries inside it already were.
+    fn guarded(
+        &mut self,
+        src: &path::Path,
+        mut op: impl FnMut(&mut Self) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut attempt = 0;
+        loop {
+            // A retry starts the entry over, so its progress is counted again.
+            let (files_done, bytes_done) = (self.files_done, self.bytes_done);
+            let err = match op(self) {
+                Ok(()) => return Ok(()),
+                Err(err)
+                    if err
+                        .get_ref()
+                        .is_some_and(|e| e.is::<JobLogError>() || e.is::<Handled>()) =>
+                {
+                    return Err(err);
+                }
+                Err(err) => err,
+            };
+
+            match self.error_policy {
+                jobs::ErrorPolicy::Abort => return Err(self.fail(src, err)),
+                jobs::ErrorPolicy::Skip => return self.skip(src, &err),
+                jobs::ErrorPolicy::Retry { attempts }
+                    if attempt < attempts && is_transient(&err) =>
+                {
+                    attempt += 1;
+                    let backoff = (RETRY_BACKOFF * 2u32.pow(attempt - 1)).min(RETRY_BACKOFF_MAX);
+                    eprintln!(
+                        "Retrying {} in {:?} ({}/{}) err={}",
+                        src.display(),
+                        backoff,
+                        attempt,
+                        attempts,
+                        err
+                    );
+                    let _ = self.events.send(runner::Event::Retrying {
+                        job_id: self.job_id.to_string(),
+                        error: jobs::FileError {
+                            path: src.to_path_buf(),
+                            error: err.to_string(),
+                        },
+                        attempt,
+                    });
+                    thread::sleep(backoff);
+                    self.files_done = files_done;
+                    self.bytes_done = bytes_done;
+                }
+                jobs::ErrorPolicy::Retry { .. } => return Err(self.fail(src, err)),
+            }
+        }
+    }
+
+    // Reports the error that is ending the job.
+    fn fail(&mut self, src: &path::Path, err: io::Error) -> io::Error {
+        let _ = self.events.send(runner::Event::Error {
+            job_id: self.job_id.to_string(),
+            error: jobs::FileError {
+                path: src.to_path_buf(),
+                error: err.to_string(),
+            },
+        });
+        io::Error::new(err.kind(), Handled(err))
+    }
+
+    fn skip(&mut self, src: &path::Path, err: &io::Error) -> io::Result<()> {
+        eprintln!("Skipping {} err={}", src.display(), err);
+        let error = jobs::FileError {
+            path: src.to_path_buf(),
+            error: err.to_string(),
+        };
+        self.log(wal::Step::Skipped(error.clone()))?;
+        self.errors.push(error.clone());
+        let _ = self.events.send(runner::Event::Skipped {
+            job_id: self.job_id.to_string(),
+            error,
+        });
+        Ok(())
+    }
+
+    fn copy_entry(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        if metadata.is_dir() {
+            self.copy_dir(src, dst, metadata)
+        } else if metadata.is_file() {
+            self.copy_file(src, dst, metadata)
+        } else if metadata.is_symlink() {
+            if self.dereference(src)? {
+                self.copy_entry(src, dst, &fs::metadata(src)?)
+            } else {
+                self.copy_node(src, dst, metadata)
+            }
+        } else if metadata.file_type().is_fifo()
+            || metadata.file_type().is_block_device()
+            || metadata.file_type().is_char_device()
+        {
+            self.copy_node(src, dst, metadata)
+        } else {
+            // A socket only means something to the process listening on it. It
+            // is reported like any other file that stays behind.
+            let err = io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is a socket", src.display()),
+            );
+            self.skip(src, &err)
+        }
+    }
+
+    fn copy_dir(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        match fs::create_dir(dst) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && dst.is_dir() => {}
+            Err(err) => return Err(err),
+        }
+
+        // Sorted so a resumed job walks the tree in the same order.
+        let mut names = fs::read_dir(src)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+
+        let mut small_files = vec![];
+        let mut others = vec![];
+        for name in names {
+            let entry_src = src.join(&name);
+            let entry_dst = dst.join(&name);
+            // Skipped on an earlier run, already reported.
+            if self.resume.errors.iter().any(|e| e.path == entry_src) {
+                continue;
+            }
+            match fs::symlink_metadata(&entry_src) {
+                Ok(entry_metadata)
+                    if self.is_small_file(&entry_src, &entry_dst, &entry_metadata) =>
+                {
+                    small_files.push((
+                        iobackend::SmallFile {
+                            src: entry_src,
+                            dst: entry_dst,
+                            len: entry_metadata.len(),
+                            mode: entry_metadata.permissions().mode(),
+                        },
+                        entry_metadata,
+                    ));
+                }
+                _ => others.push((entry_src, entry_dst)),
+            }
+        }
+
+        for batch in small_files.chunks(SMALL_FILE_BATCH) {
+            self.copy_small_files(batch)?;
+        }
+        for (entry_src, entry_dst) in others {
+            self.guarded(&entry_src, |copier| {
+                let entry_metadata = fs::symlink_metadata(&entry_src)?;
+                copier.copy_entry(&entry_src, &entry_dst, &entry_metadata)
+            })?;
+        }
+
+        // After the entries, which would bump its mtime.
+        self.preserve_attrs(src, dst, metadata);
+        Ok(())
+    }
+
+    // Whether the symlink at `src` is to be copied as what it points to.
+    fn dereference(&self, src: &path::Path) -> io::Result<bool> {
+        if self.symlink_policy == jobs::SymlinkPolicy::CopyLink {
+            return Ok(false);
+        }
+        // Broken links can only be copied as links.
+        let Ok(target) = fs::canonicalize(src) else {
+            return Ok(false);
+        };
+        if self.symlink_policy == jobs::SymlinkPolicy::DereferenceOutside
+            && target.starts_with(&self.root)
+        {
+            return Ok(false);
+        }
+        // Following a link to a directory above it would copy forever.
+        if target.is_dir()
+            && let Some(parent) = src.parent()
+            && fs::canonicalize(parent)?.starts_with(&target)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} links to {}, which contains it",
+                    src.display(),
+                    target.display()
+                ),
+            ));
+        }
+        Ok(true)
+    }
+
+    // Recreates an entry that has no data to copy: a symlink, FIFO or device
+    // node. Device nodes can only be made with privileges, without them they
+    // are skipped.
+    fn copy_node(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        if self.resume.done.contains(src) || self.kept.contains(src) {
+            self.files_done += 1;
+            return Ok(());
+        }
+        let dst = match self.targets.get(src) {
+            Some(target) => target.clone(),
+            None => match self.resolve_collision(src, dst, metadata)? {
+                Some(target) => target,
+                None => return Ok(()),
+            },
+        };
+        self.targets.insert(src.to_path_buf(), dst.clone());
+        self.started(src, &dst, 0);
+
+        let create = |dst: &path::Path| {
+            if metadata.is_symlink() {
+                unix_fs::symlink(fs::read_link(src)?, dst)
+            } else {
+                mknod(dst, metadata.mode(), metadata.rdev())
+            }
+        };
+        let created = match create(&dst) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                fs::remove_file(&dst).and_then(|()| create(&dst))
+            }
+            result => result,
+        };
+        match created {
+            Err(err)
+                if err.kind() == io::ErrorKind::PermissionDenied
+                    && (metadata.file_type().is_block_device()
+                        || metadata.file_type().is_char_device()) =>
+            {
+                return self.skip(src, &err);
+            }
+            result => result?,
+        }
+        self.preserve_attrs(src, &dst, metadata);
+        self.files_done += 1;
+        self.progress(src);
+        self.log(wal::Step::FileDone {
+            src: src.to_path_buf(),
+            dst,
+            bytes: 0,
+        })
+    }
+
+    fn copy_file(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        if self.resume.done.contains(src) || self.kept.contains(src) {
+            if self.resume.done.contains(src)
+                && let Some(target) = self.targets.get(src)
+            {
+                self.remember_link(metadata, target.clone());
+            }
+            self.files_done += 1;
+            self.bytes_done += metadata.len();
+            return Ok(());
+        }
+
+        let dst = match self.targets.get(src) {
+            Some(target) => target.clone(),
+            None => match self.resolve_collision(src, dst, metadata)? {
+                Some(target) => target,
+                None => return Ok(()),
+            },
+        };
+        let dst = dst.as_path();
+        // Overwriting a file with itself, by name, through a symlink or a bind
+        // mount, would truncate it before it is read.
+        if let Ok(dst_metadata) = fs::metadata(dst)
+            && (dst_metadata.dev(), dst_metadata.ino()) == (metadata.dev(), metadata.ino())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} and {} are the same file", src.display(), dst.display()),
+            ));
+        }
+        self.targets.insert(src.to_path_buf(), dst.to_path_buf());
+
+        if let Some(first) = self.links.get(&(metadata.dev(), metadata.ino())).cloned() {
+            match link(&first, dst) {
+                Ok(()) => {
+                    self.started(src, dst, 0);
+                    self.files_done += 1;
+                    self.bytes_done += metadata.len();
+                    self.progress(src);
+                    return self.log(wal::Step::FileDone {
+                        src: src.to_path_buf(),
+                        dst: dst.to_path_buf(),
+                        bytes: metadata.len(),
+                    });
+                }
+                // E.g. too many links; a copy of its own will do.
+                Err(err) => eprintln!(
+                    "Copying {} instead of linking to {} err={}",
+                    src.display(),
+                    first.display(),
+                    err
+                ),
+            }
+        }
+
+        let offset = self.resume_offset(src, dst);
+        self.log(wal::Step::FileStarted {
+            src: src.to_path_buf(),
+            dst: dst.to_path_buf(),
+        })?;
+        self.started(src, dst, offset);
+
+        let reader = fs::File::open(src)?;
+        let writer = if offset > 0 {
+            let writer = fs::OpenOptions::new().write(true).open(dst)?;
+            writer.set_len(offset)?;
+            writer
+        } else {
+            fs::File::create(dst)?
+        };
+
+        let mut written = offset;
+        self.bytes_done += offset;
+
+        // A whole file may be cloned in one go; anything else is copied in the
+        // kernel if the filesystems allow it.
+        self.strategy = iobackend::Strategy::CopyFileRange;
+        if offset == 0 && iobackend::reflink(&reader, &writer)? {
+            self.strategy = iobackend::Strategy::Reflink;
+            written = writer.metadata()?.len();
+            self.bytes_done += written;
+        }
+
+        // Only the data is copied. Holes are skipped, so they stay holes in
+        // the copy instead of being filled with zeros.
+        let mut checkpointed = written;
+        while let Some((data_start, data_end)) = iobackend::next_data(&reader, written)? {
+            if data_start > written {
+                self.bytes_done += data_start - written;
+                written = data_start;
+            }
+            let n = iobackend::copy_range_with(
+                &mut self.strategy,
+                self.backend,
+                &reader,
+                &writer,
+                written,
+                cmp::min(data_end - written, CHECKPOINT_INTERVAL),
+            )?;
+            if n == 0 {
+                break;
+            }
+            written += n;
+            self.bytes_done += n;
+
+            // A checkpoint may only name bytes that are already on disk.
+            if written - checkpointed >= CHECKPOINT_INTERVAL {
+                writer.sync_data()?;
+                self.log(wal::Step::Checkpoint {
+                    src: src.to_path_buf(),
+                    dst: dst.to_path_buf(),
+                    offset: written,
+                })?;
+                checkpointed = written;
+            }
+            self.progress(src);
+        }
+        // A hole at the end only exists as the file's length.
+        if written < metadata.len() {
+            writer.set_len(metadata.len())?;
+            self.bytes_done += metadata.len() - written;
+            written = metadata.len();
+        }
+
+        self.preserve_attrs(src, dst, metadata);
+        writer.sync_all()?;
+        self.files_done += 1;
+        self.progress(src);
+
+        if self.verify && !self.matches(src, dst)? {
+            return self.mismatch(src, dst);
+        }
+        self.log(wal::Step::FileDone {
+            src: src.to_path_buf(),
+            dst: dst.to_path_buf(),
+            bytes: written,
+        })?;
+        self.remember_link(metadata, dst.to_path_buf());
+        Ok(())
+    }
+
+    fn remember_link(&mut self, metadata: &fs::Metadata, dst: path::PathBuf) {
+        if metadata.nlink() > 1 {
+            self.links
+                .entry((metadata.dev(), metadata.ino()))
+                .or_insert(dst);
+        }
+    }
+
+    // Where `src` should go given what is at `dst`, or None if it stays put.
+    fn resolve_collision(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+    ) -> io::Result<Option<path::PathBuf>> {
+        let dst_metadata = match fs::symlink_metadata(dst) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Some(dst.to_path_buf()));
+            }
+            result => result?,
+        };
+
+        let collision = match self.collision_policy.resolve(metadata, &dst_metadata) {
+            Some(collision) => collision,
+            None => self.ask(src, dst, metadata, &dst_metadata)?,
+        };
+        match collision {
+            jobs::Collision::Overwrite => Ok(Some(dst.to_path_buf())),
+            jobs::Collision::Rename => Ok(Some(jobs::free_name(dst))),
+            jobs::Collision::Skip => {
+                self.log(wal::Step::Kept(src.to_path_buf()))?;
+                self.kept.insert(src.to_path_buf());
+                self.files_done += 1;
+                self.bytes_done += metadata.len();
+                let _ = self.events.send(runner::Event::Kept {
+                    job_id: self.job_id.to_string(),
+                    src: src.to_path_buf(),
+                    dst: dst.to_path_buf(),
+                });
+                self.progress(src);
+                Ok(None)
+            }
+        }
+    }
+
+    // Blocks until the UI answers. An answer for all files becomes the policy
+    // for the rest of the job.
+    fn ask(
+        &mut self,
+        src: &path::Path,
+        dst: &path::Path,
+        metadata: &fs::Metadata,
+        dst_metadata: &fs::Metadata,
+    ) -> io::Result<jobs::Collision> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let _ = self
+            .events
+            .send(runner::Event::Collision(runner::CollisionPrompt {
+                job_id: self.job_id.to_string(),
+                src: src.to_path_buf(),
+                dst: dst.to_path_buf(),
+                reply: reply_tx,
+            }));
+        let answer = reply_rx
+            .recv()
+            .map_err(|_| io::Error::other(format!("no answer about {}", dst.display())))?;
+        if answer.apply_to_all {
+            self.collision_policy = answer.policy;
+        }
+        Ok(answer
+            .policy
+            .resolve(metadata, dst_metadata)
+            .unwrap_or(jobs::Collision::Skip))
+    }
+
+    fn is_small_file(&self, src: &path::Path, dst: &path::Path, metadata: &fs::Metadata) -> bool {
+        metadata.is_file()
+            && metadata.len() <= SMALL_FILE_MAX
+            // Other names of the same file become links to its first copy.
+            && metadata.nlink() == 1
+            && !self.resume.done.contains(src)
+            && !self.targets.contains_key(src)
+            && !self.kept.contains(src)
+            // Collisions are resolved one file at a time.
+            && fs::symlink_metadata(dst).is_err()
+    }
+
+    // Copies a batch of small files through the backend in one go and logs
+    // them as started and done together. Files that fail are retried one by
+    // one under the error policy.
+    fn copy_small_files(
+        &mut self,
+        batch: &[(iobackend::SmallFile, fs::Metadata)],
+    ) -> io::Result<()> {
+        let files: Vec<iobackend::SmallFile> = batch.iter().map(|(file, _)| file.clone()).collect();
+        let started: Vec<wal::Record> = files
+            .iter()
+            .map(|file| wal::Record::Step {
+                job_id: self.job_id.to_string(),
+                step: wal::Step::FileStarted {
+                    src: file.src.clone(),
+                    dst: file.dst.clone(),
+                },
+            })
+            .collect();
+        self.job_log
+            .lock()
+            .unwrap()
+            .append_all(&started)
+            .map_err(|err| io::Error::other(JobLogError(err)))?;
+        for file in &files {
+            self.targets.insert(file.src.clone(), file.dst.clone());
+            self.started(&file.src, &file.dst, 0);
+        }
+
+        let results = self.backend.copy_small_files(&files);
+        self.strategy = iobackend::Strategy::ReadWrite;
+
+        let mut done = vec![];
+        let mut failed = vec![];
+        for ((file, metadata), result) in batch.iter().zip(results) {
+            match result {
+                Ok(bytes) => {
+                    // The mode given at creation is subject to the umask.
+                    self.preserve_attrs(&file.src, &file.dst, metadata);
+                    self.files_done += 1;
+                    self.bytes_done += bytes;
+                    if self.verify && !self.matches(&file.src, &file.dst)? {
+                        self.mismatch(&file.src, &file.dst)?;
+                        continue;
+                    }
+                    done.push(wal::Record::Step {
+                        job_id: self.job_id.to_string(),
+                        step: wal::Step::FileDone {
+                            src: file.src.clone(),
+                            dst: file.dst.clone(),
+                            bytes,
+                        },
+                    });
+                }
+                Err(_) => failed.push((file, metadata)),
+            }
+        }
+
+        if !done.is_empty() {
+            self.job_log
+                .lock()
+                .unwrap()
+                .append_all(&done)
+                .map_err(|err| io::Error::other(JobLogError(err)))?;
+        }
+        if let Some((file, _)) = batch.last() {
+            self.progress(&file.src);
+        }
+
+        for (file, metadata) in failed {
+            self.guarded(&file.src, |copier| {
+                copier.copy_file(&file.src, &file.dst, metadata)
+            })?;
+        }
+        Ok(())
+    }
+
+    // Attributes that can't be kept don't fail the file, they are reported.
+    fn preserve_attrs(&self, src: &path::Path, dst: &path::Path, metadata: &fs::Metadata) {
+        let failures = attrs::preserve(src, dst, metadata, self.preserve);
+        if failures.is_empty() {
+            return;
+        }
+        let error = failures
+            .iter()
+            .map(|f| format!("{}: {}", f.attribute, f.error))
+            .collect::<Vec<_>>()
+            .join("; ");
+        eprintln!("Not preserved on {} {}", dst.display(), error);
+        let _ = self.events.send(runner::Event::Unpreserved {
+            job_id: self.job_id.to_string(),
+            error: jobs::FileError {
+                path: dst.to_path_buf(),
+                error,
+            },
+        });
+    }
+
+    // Whether `dst` reads back the same as `src`. The copy's cached pages are
+    // dropped first so it is read back from the device, not from memory.
+    fn matches(&self, src: &path::Path, dst: &path::Path) -> io::Result<bool> {
+        let written = fs::File::open(dst)?;
+        unsafe { libc::posix_fadvise(written.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+        Ok(jobs::checksum(src)? == jobs::checksum(dst)?)
+    }
+
+    // A copy that doesn't match is reported like a skipped file, whatever the
+    // error policy, and fails the job once everything else is done.
+    fn mismatch(&mut self, src: &path::Path, dst: &path::Path) -> io::Result<()> {
+        self.verify_failures += 1;
+        let err = io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("checksum of {} does not match", dst.display()),
+        );
+        self.skip(src, &err)
+    }
+
+    // Where to pick up a file that was being written when the job was
+    // interrupted. Starts over unless the partial file still matches the source.
+    fn resume_offset(&self, src: &path::Path, dst: &path::Path) -> u64 {
+        match &self.resume.checkpoint {
+            Some(checkpoint) if checkpoint.src == src && checkpoint.dst == dst => {
+                match jobs::verify_prefix(src, dst, checkpoint.offset) {
+                    Ok(true) => checkpoint.offset,
+                    _ => {
+                        eprintln!("Restarting {} from the beginning", dst.display());
+                        0
+                    }
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn log(&self, step: wal::Step) -> io::Result<()> {
+        self.job_log
+            .lock()
+            .unwrap()
+            .append(&wal::Record::Step {
+                job_id: self.job_id.to_string(),
+                step,
+            })
+            .map_err(|err| io::Error::other(JobLogError(err)))
+    }
+
+    fn started(&self, src: &path::Path, dst: &path::Path, offset: u64) {
+        let _ = self.events.send(runner::Event::FileStarted {
+            job_id: self.job_id.to_string(),
+            src: src.to_path_buf(),
+            dst: dst.to_path_buf(),
+            offset,
+        });
+    }
+
+    fn progress(&self, current: &path::Path) {
+        let _ = self.events.send(runner::Event::Progress {
+            job_id: self.job_id.to_string(),
+            files_done: self.files_done,
+            bytes_done: self.bytes_done,
+            current: current.to_path_buf(),
+            strategy: self.strategy,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs::FileExt;
+
+    // What a Copier borrows, around a fresh directory to copy in.
+    struct Fixture {
+        root: path::PathBuf,
+        job_log: Mutex<wal::Wal>,
+        events_tx: mpsc::Sender<runner::Event>,
+        events_rx: mpsc::Receiver<runner::Event>,
+        resume: jobs::Resume,
+        backend: iobackend::StdBackend,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let root = env::temp_dir().join(format!("files_please-{}-{}", name, uuidv7::create()));
+            fs::create_dir_all(&root).unwrap();
+            let (job_log, _) = wal::Wal::open(&root.join("jobs.wal")).unwrap();
+            let (events_tx, events_rx) = mpsc::channel();
+            Fixture {
+                root,
+                job_log: Mutex::new(job_log),
+                events_tx,
+                events_rx,
+                resume: jobs::Resume::default(),
+                backend: iobackend::StdBackend::new(),
+            }
+        }
+
+        fn copier(&mut self, options: jobs::TransferOptions) -> Copier<'_> {
+            Copier::new(
+                "job",
+                &self.job_log,
+                &self.events_tx,
+                &self.resume,
+                options,
+                &mut self.backend,
+            )
+        }
+    }
+
+    // Tests override what they are about.
+    fn options() -> jobs::TransferOptions {
+        jobs::TransferOptions {
+            error_policy: jobs::ErrorPolicy::Abort,
+            collision_policy: jobs::CollisionPolicy::Overwrite,
+            symlink_policy: jobs::SymlinkPolicy::CopyLink,
+            verify: false,
+            preserve: jobs::Preserve::basic(),
+        }
+    }
+
+    #[test]
+    fn copies_tree_into_existing_directory() {
+        let mut fixture = Fixture::new("copy");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(src.join("a.txt"), b"alpha").unwrap();
+        fs::write(
+            src.join("nested").join("b.bin"),
+            vec![7u8; CHECKPOINT_INTERVAL as usize + 1],
+        )
+        .unwrap();
+
+        let target = resolve_target(&src, &dst);
+        assert_eq!(target, dst.join("src"));
+        {
+            let mut copier = fixture.copier(jobs::TransferOptions {
+                verify: true,
+                ..options()
+            });
+            copier.copy(&src, &target).unwrap();
+            copier.verified().unwrap();
+            assert!(
+                copier
+                    .matches(&src.join("a.txt"), &target.join("a.txt"))
+                    .unwrap()
+            );
+            fs::write(target.join("a.txt"), b"alphA").unwrap();
+            assert!(
+                !copier
+                    .matches(&src.join("a.txt"), &target.join("a.txt"))
+                    .unwrap()
+            );
+            fs::write(target.join("a.txt"), b"alpha").unwrap();
+        }
+
+        assert_eq!(fs::read(target.join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(
+            fs::read(target.join("nested").join("b.bin")).unwrap(),
+            fs::read(src.join("nested").join("b.bin")).unwrap()
+        );
+        assert!(fixture.events_rx.try_iter().count() > 0);
+
+        assert!(
+            fixture
+                .copier(options())
+                .copy(&src, &src.join("nested"))
+                .is_err()
+        );
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn every_entry_is_reported_as_started() {
+        let mut fixture = Fixture::new("started");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a"), b"small").unwrap();
+        fs::hard_link(src.join("a"), src.join("b")).unwrap();
+        fs::write(src.join("c"), b"batched").unwrap();
+        fs::write(src.join("d"), b"batched too").unwrap();
+        unix_fs::symlink("c", src.join("link")).unwrap();
+
+        fixture.copier(options()).copy(&src, &dst).unwrap();
+
+        let mut started = vec![];
+        let (mut files, mut files_done) = (0, 0);
+        for event in fixture.events_rx.try_iter() {
+            match event {
+                runner::Event::FileStarted { src, dst, .. } => started.push((src, dst)),
+                runner::Event::Totals { files: total, .. } => files = total,
+                runner::Event::Progress {
+                    files_done: done, ..
+                } => files_done = done,
+                _ => {}
+            }
+        }
+        // Everything is counted, links and all, and only once.
+        assert_eq!((files_done, files), (5, 5));
+        started.sort();
+        let expected: Vec<(path::PathBuf, path::PathBuf)> = ["a", "b", "c", "d", "link"]
+            .iter()
+            .map(|name| (src.join(name), dst.join(name)))
+            .collect();
+        assert_eq!(started, expected);
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn sparse_files_keep_their_holes() {
+        let mut fixture = Fixture::new("sparse");
+        let src = fixture.root.join("disk.img");
+        let dst = fixture.root.join("copy.img");
+        let len = 4 * CHECKPOINT_INTERVAL;
+        {
+            let file = fs::File::create(&src).unwrap();
+            file.set_len(len).unwrap();
+            file.write_all_at(b"boot", 0).unwrap();
+            file.write_all_at(&[9u8; 4096], 2 * CHECKPOINT_INTERVAL)
+                .unwrap();
+        }
+
+        fixture.copier(options()).copy(&src, &dst).unwrap();
+
+        assert_eq!(fs::read(&dst).unwrap(), fs::read(&src).unwrap());
+        // Only where the filesystem has holes to begin with.
+        let allocated = |p: &path::Path| fs::metadata(p).unwrap().blocks() * 512;
+        if allocated(&src) < len {
+            assert!(allocated(&dst) < len, "copy allocated {}", allocated(&dst));
+        }
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn hard_links_stay_linked() {
+        let mut fixture = Fixture::new("links");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a"), b"shared").unwrap();
+        fs::hard_link(src.join("a"), src.join("b")).unwrap();
+        fs::write(src.join("c"), b"own").unwrap();
+
+        fixture.copier(options()).copy(&src, &dst).unwrap();
+
+        let ino = |name: &str| fs::metadata(dst.join(name)).unwrap().ino();
+        assert_eq!(fs::read(dst.join("b")).unwrap(), b"shared");
+        assert_eq!(ino("a"), ino("b"));
+        assert_ne!(ino("a"), ino("c"));
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn symlinks_follow_the_symlink_policy() {
+        let mut fixture = Fixture::new("symlinks");
+        let root = fixture.root.clone();
+        let src = root.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(root.join("outside"), b"outside").unwrap();
+        fs::write(src.join("a"), b"inside").unwrap();
+        unix_fs::symlink("a", src.join("to_inside")).unwrap();
+        unix_fs::symlink(root.join("outside"), src.join("to_outside")).unwrap();
+        unix_fs::symlink("missing", src.join("broken")).unwrap();
+
+        let mut copy_with = |symlink_policy, dst: &path::Path| {
+            fixture
+                .copier(jobs::TransferOptions {
+                    symlink_policy,
+                    ..options()
+                })
+                .copy(&src, dst)
+                .unwrap();
+        };
+        let is_link = |p: path::PathBuf| fs::symlink_metadata(p).unwrap().is_symlink();
+
+        let dst = root.join("links");
+        copy_with(jobs::SymlinkPolicy::CopyLink, &dst);
+        assert!(is_link(dst.join("to_inside")));
+        assert!(is_link(dst.join("to_outside")));
+        assert_eq!(
+            fs::read_link(dst.join("broken")).unwrap(),
+            path::Path::new("missing")
+        );
+
+        let dst = root.join("outside_followed");
+        copy_with(jobs::SymlinkPolicy::DereferenceOutside, &dst);
+        assert!(is_link(dst.join("to_inside")));
+        assert!(!is_link(dst.join("to_outside")));
+        assert_eq!(fs::read(dst.join("to_outside")).unwrap(), b"outside");
+
+        let dst = root.join("followed");
+        copy_with(jobs::SymlinkPolicy::Dereference, &dst);
+        assert!(!is_link(dst.join("to_inside")));
+        assert!(is_link(dst.join("broken")));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn nested_guards_retry_once() {
+        let mut fixture = Fixture::new("retry");
+        let src = fixture.root.join("a");
+        let mut attempts = 0;
+        {
+            let mut copier = fixture.copier(jobs::TransferOptions {
+                error_policy: jobs::ErrorPolicy::Retry { attempts: 2 },
+                ..options()
+            });
+            let err = copier
+                .guarded(&src, |copier| {
+                    copier.guarded(&src, |_| {
+                        attempts += 1;
+                        Err(io::ErrorKind::TimedOut.into())
+                    })
+                })
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        }
+
+        assert_eq!(attempts, 3);
+        let errors = fixture
+            .events_rx
+            .try_iter()
+            .filter(|event| matches!(event, runner::Event::Error { .. }))
+            .count();
+        assert_eq!(errors, 1);
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn fifos_are_recreated_not_read() {
+        let mut fixture = Fixture::new("fifo");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(&src).unwrap();
+        mknod(&src.join("pipe"), libc::S_IFIFO | 0o600, 0).unwrap();
+
+        fixture.copier(options()).copy(&src, &dst).unwrap();
+
+        let copied = fs::symlink_metadata(dst.join("pipe")).unwrap();
+        assert!(copied.file_type().is_fifo());
+        assert_eq!(copied.mode() & 0o777, 0o600);
+        let (mut files, mut files_done) = (0, 0);
+        for event in fixture.events_rx.try_iter() {
+            match event {
+                runner::Event::Totals { files: total, .. } => files = total,
+                runner::Event::Progress {
+                    files_done: done, ..
+                } => files_done = done,
+                _ => {}
+            }
+        }
+        assert_eq!((files_done, files), (1, 1));
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn sockets_are_left_behind() {
+        let mut fixture = Fixture::new("socket");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(&src).unwrap();
+        let _listener = std::os::unix::net::UnixListener::bind(src.join("sock")).unwrap();
+
+        let mut copier = fixture.copier(options());
+        copier.copy(&src, &dst).unwrap();
+
+        assert!(copier.left_behind().contains(src.join("sock").as_path()));
+        assert!(!dst.join("sock").exists());
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn moves_rename_over_what_the_policy_replaces() {
+        let mut fixture = Fixture::new("move");
+        let src = fixture.root.join("new");
+        let dst = fixture.root.join("old");
+        fs::write(&src, b"new").unwrap();
+        fs::write(&dst, b"old").unwrap();
+        let ino = fs::metadata(&src).unwrap().ino();
+
+        crate::mover::move_path(&mut fixture.copier(options()), &src, &dst).unwrap();
+
+        assert!(!src.exists());
+        assert_eq!(fs::read(&dst).unwrap(), b"new");
+        // Renamed, not copied.
+        assert_eq!(fs::metadata(&dst).unwrap().ino(), ino);
+
+        fs::write(&src, b"newer").unwrap();
+        let mut copier = fixture.copier(jobs::TransferOptions {
+            collision_policy: jobs::CollisionPolicy::Skip,
+            ..options()
+        });
+        crate::mover::move_path(&mut copier, &src, &dst).unwrap();
+        assert_eq!(fs::read(&src).unwrap(), b"newer");
+        assert_eq!(fs::read(&dst).unwrap(), b"new");
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+
+    #[test]
+    fn existing_targets_follow_the_collision_policy() {
+        let mut fixture = Fixture::new("collide");
+        let src = fixture.root.join("src");
+        let dst = fixture.root.join("dst");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(src.join("same.txt"), b"new").unwrap();
+        fs::write(src.join("other.tar.gz"), b"longer").unwrap();
+
+        let mut copy_with = |collision_policy| {
+            fs::write(dst.join("same.txt"), b"old").unwrap();
+            fs::write(dst.join("other.tar.gz"), b"old").unwrap();
+            let mut copier = fixture.copier(jobs::TransferOptions {
+                collision_policy,
+                ..options()
+            });
+            copier.copy(&src, &dst).unwrap();
+            copier.left_behind().len()
+        };
+
+        assert_eq!(copy_with(jobs::CollisionPolicy::Skip), 2);
+        assert_eq!(fs::read(dst.join("same.txt")).unwrap(), b"old");
+
+        assert_eq!(
+            copy_with(jobs::CollisionPolicy::OverwriteIfDifferentSize),
+            1
+        );
+        assert_eq!(fs::read(dst.join("same.txt")).unwrap(), b"old");
+        assert_eq!(fs::read(dst.join("other.tar.gz")).unwrap(), b"longer");
+
+        assert_eq!(copy_with(jobs::CollisionPolicy::Rename), 0);
+        assert_eq!(fs::read(dst.join("same.txt")).unwrap(), b"old");
+        assert_eq!(fs::read(dst.join("same (1).txt")).unwrap(), b"new");
+        assert_eq!(fs::read(dst.join("other.tar (1).gz")).unwrap(), b"longer");
+
+        unix_fs::symlink(src.join("same.txt"), dst.join("link.txt")).unwrap();
+        let mut copier = fixture.copier(options());
+        assert!(
+            copier
+                .copy(&src.join("same.txt"), &src.join("same.txt"))
+                .is_err()
+        );
+        assert!(
+            copier
+                .copy(&src.join("same.txt"), &dst.join("link.txt"))
+                .is_err()
+        );
+        assert_eq!(fs::read(src.join("same.txt")).unwrap(), b"new");
+
+        let _ = fs::remove_dir_all(&fixture.root);
+    }
+}
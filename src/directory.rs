@@ -1,78 +1,488 @@
+use std::cmp;
+use std::collections;
+use std::ffi;
 use std::fs;
-use std::os::unix::fs::MetadataExt;
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path;
+use std::ptr;
+use std::str;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
 
 #[derive(Clone, PartialEq)]
 pub enum EntryKind {
     Dir,
     File,
+    // `broken` when there is nothing at `target`.
+    Symlink { target: path::PathBuf, broken: bool },
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+fn special_kind(file_type: &fs::FileType) -> Option<EntryKind> {
+    if file_type.is_fifo() {
+        Some(EntryKind::Fifo)
+    } else if file_type.is_socket() {
+        Some(EntryKind::Socket)
+    } else if file_type.is_block_device() {
+        Some(EntryKind::BlockDevice)
+    } else if file_type.is_char_device() {
+        Some(EntryKind::CharDevice)
+    } else {
+        None
+    }
+}
+
+// What statx says about an entry itself, not what a symlink points to.
+#[derive(Clone)]
+pub struct Stat {
+    pub size: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub ino: u64,
+    // Seconds since the epoch.
+    pub mtime: i64,
+    pub ctime: i64,
+    // Only where the filesystem records it.
+    pub btime: Option<i64>,
+}
+
+pub fn stat(p: &path::Path) -> io::Result<Stat> {
+    let c_p = ffi::CString::new(p.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stx: libc::statx = unsafe { mem::zeroed() };
+    let result = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_p.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+            libc::STATX_BASIC_STATS | libc::STATX_BTIME,
+            &mut stx,
+        )
+    };
+    if result < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOSYS) {
+            return Err(err);
+        }
+        // Kernels before 4.11 have no statx, and no birth times to give.
+        let metadata = fs::symlink_metadata(p)?;
+        return Ok(Stat {
+            size: metadata.len(),
+            mode: metadata.mode(),
+            nlink: metadata.nlink() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            ino: metadata.ino(),
+            mtime: metadata.mtime(),
+            ctime: metadata.ctime(),
+            btime: None,
+        });
+    }
+    Ok(Stat {
+        size: stx.stx_size,
+        mode: stx.stx_mode as u32,
+        nlink: stx.stx_nlink,
+        uid: stx.stx_uid,
+        gid: stx.stx_gid,
+        ino: stx.stx_ino,
+        mtime: stx.stx_mtime.tv_sec,
+        ctime: stx.stx_ctime.tv_sec,
+        btime: (stx.stx_mask & libc::STATX_BTIME != 0).then_some(stx.stx_btime.tv_sec),
+    })
+}
+
+// User and group names by id, looked up once per listing.
+#[derive(Default)]
+struct Names {
+    users: collections::HashMap<u32, String>,
+    groups: collections::HashMap<u32, String>,
+}
+
+impl Names {
+    fn user(&mut self, uid: u32) -> String {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| user_name(uid).unwrap_or_else(|| uid.to_string()))
+            .clone()
+    }
+
+    fn group(&mut self, gid: u32) -> String {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| group_name(gid).unwrap_or_else(|| gid.to_string()))
+            .clone()
+    }
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 1024];
+    let mut found = ptr::null_mut();
+    loop {
+        let rc =
+            unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+        if rc == libc::ERANGE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || found.is_null() {
+            return None;
+        }
+        let name = unsafe { ffi::CStr::from_ptr(passwd.pw_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    let mut group: libc::group = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 1024];
+    let mut found = ptr::null_mut();
+    loop {
+        let rc =
+            unsafe { libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut found) };
+        if rc == libc::ERANGE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || found.is_null() {
+            return None;
+        }
+        let name = unsafe { ffi::CStr::from_ptr(group.gr_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
 }
 
 #[derive(Clone)]
 pub struct Entry {
     pub kind: EntryKind,
     pub name: path::PathBuf,
-    pub inode: u64,
+    pub stat: Stat,
+    pub owner: String,
+    pub group: String,
 }
 
 impl Entry {
-    pub fn new(kind: EntryKind, name: path::PathBuf, inode: u64) -> Entry {
+    pub fn new(
+        kind: EntryKind,
+        name: path::PathBuf,
+        stat: Stat,
+        owner: String,
+        group: String,
+    ) -> Entry {
         Entry {
-            kind: kind,
-            name: name,
-            inode: inode,
+            kind,
+            name,
+            stat,
+            owner,
+            group,
+        }
+    }
+
+    // Whether moving into the entry shows a directory, following symlinks.
+    pub fn is_dir(&self, dir: &path::Path) -> bool {
+        match &self.kind {
+            EntryKind::Dir => true,
+            EntryKind::Symlink { broken: false, .. } => dir.join(&self.name).is_dir(),
+            _ => false,
         }
     }
 }
 
-#[derive(Clone)]
-pub struct Entries {
-    pub absolute_path: path::PathBuf,
-    pub entries: Vec<Entry>,
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    // Largest first.
+    Size,
+    // Newest first.
+    Modified,
+    Extension,
+    Kind,
 }
 
-impl Entries {
-    pub fn new(absolute_path: path::PathBuf, read_dir_it: fs::ReadDir) -> Entries {
-        let mut entries = Entries {
-            absolute_path: absolute_path,
-            entries: vec![],
+impl SortKey {
+    pub fn next(self) -> SortKey {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Extension,
+            SortKey::Extension => SortKey::Kind,
+            SortKey::Kind => SortKey::Name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    // Applies in either direction.
+    pub dirs_first: bool,
+    pub reverse: bool,
+    pub case_sensitive: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> SortOrder {
+        SortOrder {
+            key: SortKey::Name,
+            dirs_first: true,
+            reverse: false,
+            case_sensitive: false,
+        }
+    }
+}
+
+impl SortOrder {
+    pub fn compare(&self, a: &Entry, b: &Entry) -> cmp::Ordering {
+        if self.dirs_first {
+            let dirs = (b.kind == EntryKind::Dir).cmp(&(a.kind == EntryKind::Dir));
+            if dirs.is_ne() {
+                return dirs;
+            }
+        }
+        let by_name = || {
+            natural_cmp(
+                &a.name.to_string_lossy(),
+                &b.name.to_string_lossy(),
+                self.case_sensitive,
+            )
+            .then_with(|| a.name.cmp(&b.name))
+        };
+        let order = match self.key {
+            SortKey::Name => by_name(),
+            SortKey::Size => b.stat.size.cmp(&a.stat.size).then_with(by_name),
+            SortKey::Modified => b.stat.mtime.cmp(&a.stat.mtime).then_with(by_name),
+            SortKey::Extension => {
+                let extension = |e: &Entry| {
+                    e.name
+                        .extension()
+                        .map(|x| x.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                };
+                natural_cmp(&extension(a), &extension(b), self.case_sensitive).then_with(by_name)
+            }
+            SortKey::Kind => kind_rank(&a.kind)
+                .cmp(&kind_rank(&b.kind))
+                .then_with(by_name),
         };
+        if self.reverse { order.reverse() } else { order }
+    }
+}
+
+fn kind_rank(kind: &EntryKind) -> u8 {
+    match kind {
+        EntryKind::Dir => 0,
+        EntryKind::Symlink { .. } => 1,
+        EntryKind::File => 2,
+        EntryKind::Fifo => 3,
+        EntryKind::Socket => 4,
+        EntryKind::BlockDevice => 5,
+        EntryKind::CharDevice => 6,
+    }
+}
+
+// Compares runs of digits by their value, so "file9" comes before "file10".
+pub fn natural_cmp(a: &str, b: &str, case_sensitive: bool) -> cmp::Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return cmp::Ordering::Equal,
+            (None, Some(_)) => return cmp::Ordering::Less,
+            (Some(_), None) => return cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |chars: &mut iter::Peekable<str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        run.push(c);
+                    }
+                    run.trim_start_matches('0').to_string()
+                };
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let order = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if order.is_ne() {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let order = if case_sensitive {
+                    x.cmp(&y)
+                } else {
+                    x.to_lowercase().cmp(y.to_lowercase())
+                };
+                if order.is_ne() {
+                    return order;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+// How many entries the worker reads before handing them over, unless it has
+// been a while since the last batch.
+const BATCH_SIZE: usize = 1024;
+const BATCH_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+// The entries of a directory, read and stat'ed on a worker thread and handed
+// over in batches. Dropping it stops the worker.
+pub struct Loading {
+    pub absolute_path: path::PathBuf,
+    batches: mpsc::Receiver<Vec<Entry>>,
+}
 
-        for entry in read_dir_it {
-            if let Ok(entry) = entry {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_dir() {
-                        if let Some(entry_name) = entry.path().file_name() {
-                            if let Ok(metadata) = entry.metadata() {
-                                entries.entries.push(Entry::new(
-                                    EntryKind::Dir,
-                                    path::PathBuf::from(entry_name),
-                                    metadata.ino(),
-                                ));
-                            }
-                        }
-                    } else if file_type.is_file() {
-                        if let Some(entry_name) = entry.path().file_name() {
-                            if let Ok(metadata) = entry.metadata() {
-                                entries.entries.push(Entry::new(
-                                    EntryKind::File,
-                                    path::PathBuf::from(entry_name),
-                                    metadata.ino(),
-                                ));
-                            }
-                        }
-                    } else {
-                        if let Some(entry_name) = entry.path().file_name() {
-                            eprintln!(
-                                "Unhandled file type {}",
-                                path::PathBuf::from(entry_name).display()
-                            );
-                        }
+impl Loading {
+    pub fn open(absolute_path: path::PathBuf) -> io::Result<Loading> {
+        let read_dir_it = fs::read_dir(&absolute_path)?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut names = Names::default();
+            let mut batch = vec![];
+            let mut last_sent = time::Instant::now();
+            for entry in read_dir_it.flatten() {
+                if let Ok(file_type) = entry.file_type()
+                    && let Some(entry) = read_entry(&entry.path(), file_type, &mut names)
+                {
+                    batch.push(entry);
+                }
+                if (batch.len() >= BATCH_SIZE || last_sent.elapsed() >= BATCH_INTERVAL)
+                    && !batch.is_empty()
+                {
+                    if tx.send(mem::take(&mut batch)).is_err() {
+                        // Navigated away.
+                        return;
                     }
+                    last_sent = time::Instant::now();
                 }
             }
+            let _ = tx.send(batch);
+        });
+        Ok(Loading {
+            absolute_path,
+            batches: rx,
+        })
+    }
+
+    // The entries read since the last call, and whether that was all of them.
+    pub fn poll(&self) -> (Vec<Entry>, bool) {
+        let mut entries = vec![];
+        loop {
+            match self.batches.try_recv() {
+                Ok(batch) => entries.extend(batch),
+                Err(mpsc::TryRecvError::Empty) => return (entries, false),
+                Err(mpsc::TryRecvError::Disconnected) => return (entries, true),
+            }
         }
+    }
+}
+
+// The entry at `entry_path` as it is now, None if there is nothing there.
+pub fn lookup(entry_path: &path::Path) -> Option<Entry> {
+    let file_type = fs::symlink_metadata(entry_path).ok()?.file_type();
+    read_entry(entry_path, file_type, &mut Names::default())
+}
+
+fn read_entry(
+    entry_path: &path::Path,
+    file_type: fs::FileType,
+    names: &mut Names,
+) -> Option<Entry> {
+    let entry_name = entry_path.file_name()?;
+    let kind = if file_type.is_dir() {
+        EntryKind::Dir
+    } else if file_type.is_file() {
+        EntryKind::File
+    } else if file_type.is_symlink() {
+        let target = fs::read_link(entry_path).ok()?;
+        EntryKind::Symlink {
+            target,
+            broken: fs::metadata(entry_path).is_err(),
+        }
+    } else if let Some(kind) = special_kind(&file_type) {
+        kind
+    } else {
+        eprintln!(
+            "Unhandled file type {}",
+            path::PathBuf::from(entry_name).display()
+        );
+        return None;
+    };
+    let stat = stat(entry_path).ok()?;
+    let owner = names.user(stat.uid);
+    let group = names.group(stat.gid);
+    Some(Entry::new(
+        kind,
+        path::PathBuf::from(entry_name),
+        stat,
+        owner,
+        group,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs as unix_fs;
+
+    #[test]
+    fn entries_carry_their_metadata() {
+        let dir = env::temp_dir().join(format!("files_please-dir-{}", uuidv7::create()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("file"), b"hello").unwrap();
+        unix_fs::symlink("nowhere", dir.join("link")).unwrap();
+
+        let loading = Loading::open(dir.clone()).unwrap();
+        let mut entries = vec![];
+        loop {
+            let (batch, done) = loading.poll();
+            entries.extend(batch);
+            if done {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        let entry = |name: &str| {
+            entries
+                .iter()
+                .find(|e| e.name == path::Path::new(name))
+                .unwrap()
+        };
+
+        let file = entry("file");
+        assert!(file.kind == EntryKind::File);
+        assert_eq!(file.stat.size, 5);
+        assert_eq!(file.stat.nlink, 1);
+        assert_eq!(file.stat.ino, fs::metadata(dir.join("file")).unwrap().ino());
+        assert!(!file.owner.is_empty());
+        assert!(entry("sub").kind == EntryKind::Dir);
+        assert!(
+            entry("link").kind
+                == EntryKind::Symlink {
+                    target: path::PathBuf::from("nowhere"),
+                    broken: true,
+                }
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 
-        entries
+    #[test]
+    fn names_sort_naturally() {
+        let mut names = vec!["file10", "File2", "file1", "file02b", "a"];
+        names.sort_by(|a, b| natural_cmp(a, b, false));
+        assert_eq!(names, ["a", "file1", "File2", "file02b", "file10"]);
+        names.sort_by(|a, b| natural_cmp(a, b, true));
+        assert_eq!(names, ["File2", "a", "file1", "file02b", "file10"]);
     }
 }
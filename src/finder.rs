@@ -0,0 +1,520 @@
+use std::cmp;
+use std::fs;
+use std::mem;
+use std::path;
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+// Stop collecting paths after this many; ranking more gets sluggish.
+const MAX_CANDIDATES: usize = 500_000;
+
+// How many paths the walk collects before handing them over.
+const BATCH_SIZE: usize = 1024;
+
+// How many candidates are scored per poll, so a keystroke with half a million
+// of them doesn't freeze the window. The rest are scored on the next polls.
+const RANK_BUDGET: usize = 20_000;
+
+// Fuzzy matches the paths below `root` against a query, while a background
+// thread is still walking the tree.
+pub struct Finder {
+    pub root: path::PathBuf,
+    pub gitignore: bool,
+    query: String,
+    // Relative to `root`.
+    candidates: Vec<path::PathBuf>,
+    // Best first, as (score, index into candidates).
+    matches: Vec<(i64, usize)>,
+    // The candidates before this one have been scored against the query.
+    scored: usize,
+    // Dropping it stops the walk.
+    walk: Option<mpsc::Receiver<Vec<path::PathBuf>>>,
+}
+
+impl Finder {
+    pub fn new(root: path::PathBuf, gitignore: bool) -> Finder {
+        let (tx, rx) = mpsc::channel();
+        let walk_root = root.clone();
+        thread::spawn(move || walk(&walk_root, gitignore, &tx));
+        Finder {
+            root,
+            gitignore,
+            query: String::new(),
+            candidates: vec![],
+            matches: vec![],
+            scored: 0,
+            walk: Some(rx),
+        }
+    }
+
+    // Takes in what the walk found since the last call and scores some more
+    // of the candidates.
+    pub fn poll(&mut self) {
+        if let Some(walk) = &self.walk {
+            loop {
+                match walk.try_recv() {
+                    Ok(batch) => self.candidates.extend(batch),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        self.walk = None;
+                        break;
+                    }
+                }
+            }
+            if self.candidates.len() >= MAX_CANDIDATES {
+                self.candidates.truncate(MAX_CANDIDATES);
+                self.walk = None;
+            }
+        }
+        self.rank();
+    }
+
+    pub fn walking(&self) -> bool {
+        self.walk.is_some()
+    }
+
+    // Whether there are candidates left to score against the query.
+    pub fn ranking(&self) -> bool {
+        self.scored < self.candidates.len()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn edit_query(&mut self, edit: impl FnOnce(&mut String)) {
+        let previous = self.query.clone();
+        edit(&mut self.query);
+        if self.query.starts_with(&previous) {
+            // A longer query only matches what the shorter one did, so only
+            // the matches need scoring again.
+            let (query, candidates) = (&self.query, &self.candidates);
+            self.matches.retain_mut(|(score, index)| {
+                match self::score(query, &candidates[*index].to_string_lossy()) {
+                    Some(new_score) => {
+                        *score = new_score;
+                        true
+                    }
+                    None => false,
+                }
+            });
+            let candidates = &self.candidates;
+            self.matches.sort_by(|a, b| compare(candidates, a, b));
+        } else {
+            self.matches.clear();
+            self.scored = 0;
+        }
+        self.rank();
+    }
+
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    // Best first, relative to `root`.
+    pub fn matches(&self) -> impl Iterator<Item = &path::Path> {
+        self.matches
+            .iter()
+            .map(|(_, index)| self.candidates[*index].as_path())
+    }
+
+    // Scores the next candidates, up to the budget, and merges them into the
+    // matches, which are already in order.
+    fn rank(&mut self) {
+        let end = self.candidates.len().min(self.scored + RANK_BUDGET);
+        if self.scored == end {
+            return;
+        }
+        let candidates = &self.candidates;
+        let mut fresh: Vec<(i64, usize)> = (self.scored..end)
+            .filter_map(|index| {
+                score(&self.query, &candidates[index].to_string_lossy()).map(|s| (s, index))
+            })
+            .collect();
+        fresh.sort_by(|a, b| compare(candidates, a, b));
+        self.scored = end;
+
+        let old = mem::take(&mut self.matches);
+        self.matches.reserve(old.len() + fresh.len());
+        let (mut old, mut fresh) = (old.into_iter().peekable(), fresh.into_iter().peekable());
+        loop {
+            let next = match (old.peek(), fresh.peek()) {
+                (Some(a), Some(b)) if compare(candidates, a, b).is_le() => old.next(),
+                (_, Some(_)) => fresh.next(),
+                (Some(_), None) => old.next(),
+                (None, None) => break,
+            };
+            self.matches.extend(next);
+        }
+    }
+}
+
+// Best score first, then the shorter path, then by name.
+fn compare(
+    candidates: &[path::PathBuf],
+    (a_score, a): &(i64, usize),
+    (b_score, b): &(i64, usize),
+) -> cmp::Ordering {
+    let (a, b) = (&candidates[*a], &candidates[*b]);
+    b_score
+        .cmp(a_score)
+        .then_with(|| a.as_os_str().len().cmp(&b.as_os_str().len()))
+        .then_with(|| a.cmp(b))
+}
+
+// Sends every file and directory below `root`, relative to it, in batches.
+// Symlinks are listed but not followed.
+fn walk(root: &path::Path, gitignore: bool, tx: &mpsc::Sender<Vec<path::PathBuf>>) {
+    let mut batch = vec![];
+    let mut stack = vec![(root.to_path_buf(), Vec::<Arc<Ignore>>::new())];
+    while let Some((dir, mut ignores)) = stack.pop() {
+        if gitignore && let Some(ignore) = Ignore::load(&dir) {
+            ignores.push(Arc::new(ignore));
+        }
+        let Ok(read_dir_it) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir_it.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let entry_path = entry.path();
+            let is_dir = file_type.is_dir();
+            if gitignore && (entry.file_name() == ".git" || ignored(&ignores, &entry_path, is_dir))
+            {
+                continue;
+            }
+            if let Ok(relative) = entry_path.strip_prefix(root) {
+                batch.push(relative.to_path_buf());
+            }
+            if is_dir {
+                stack.push((entry_path, ignores.clone()));
+            }
+            if batch.len() >= BATCH_SIZE && tx.send(mem::take(&mut batch)).is_err() {
+                // Nobody is looking anymore.
+                return;
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let _ = tx.send(batch);
+    }
+}
+
+// Whether the innermost .gitignore with an opinion on `p` excludes it.
+fn ignored(ignores: &[Arc<Ignore>], p: &path::Path, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        if let Some(ignored) = ignore.matches(p, is_dir) {
+            return ignored;
+        }
+    }
+    false
+}
+
+// The patterns of one .gitignore, which apply below the directory it is in.
+struct Ignore {
+    base: path::PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    glob: String,
+    // "!pattern" re-includes what an earlier pattern excluded.
+    negated: bool,
+    // "pattern/" only matches directories.
+    dir_only: bool,
+    // Patterns with a slash before the end match the path from `base`, the
+    // others just the name.
+    anchored: bool,
+}
+
+impl Ignore {
+    fn load(dir: &path::Path) -> Option<Ignore> {
+        let text = fs::read_to_string(dir.join(".gitignore")).ok()?;
+        Some(Ignore::parse(dir.to_path_buf(), &text))
+    }
+
+    fn parse(base: path::PathBuf, text: &str) -> Ignore {
+        let mut patterns = vec![];
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            patterns.push(Pattern {
+                glob: line.trim_start_matches('/').to_string(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+        Ignore { base, patterns }
+    }
+
+    // Some(true) when the last pattern matching `p` excludes it, None when no
+    // pattern matches.
+    fn matches(&self, p: &path::Path, is_dir: bool) -> Option<bool> {
+        let relative = p.strip_prefix(&self.base).ok()?.to_string_lossy();
+        let name = relative.rsplit('/').next().unwrap_or(&relative);
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| {
+                (is_dir || !pattern.dir_only)
+                    && glob_matches(
+                        pattern.glob.as_bytes(),
+                        if pattern.anchored { &relative } else { name }.as_bytes(),
+                    )
+            })
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+// Shell-style matching as in .gitignore: `*` and `?` stop at slashes, `**`
+// doesn't, `[a-z]` and `[!a-z]` are character classes.
+fn glob_matches(glob: &[u8], text: &[u8]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some(b'*') if glob.get(1) == Some(&b'*') => {
+            let rest = &glob[2..];
+            // "**/" also matches no directories at all.
+            let rest_after_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|i| {
+                glob_matches(rest, &text[i..]) || glob_matches(rest_after_slash, &text[i..])
+            })
+        }
+        Some(b'*') => {
+            let rest = &glob[1..];
+            for i in 0..=text.len() {
+                if glob_matches(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            matches!(text.first(), Some(c) if *c != b'/') && glob_matches(&glob[1..], &text[1..])
+        }
+        Some(b'[') => match (class_end(glob), text.first()) {
+            (Some(end), Some(c)) => {
+                class_matches(&glob[1..end], *c) && glob_matches(&glob[end + 1..], &text[1..])
+            }
+            // An unclosed bracket is just a bracket.
+            (None, Some(b'[')) => glob_matches(&glob[1..], &text[1..]),
+            _ => false,
+        },
+        Some(b'\\') if glob.len() > 1 => {
+            text.first() == Some(&glob[1]) && glob_matches(&glob[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_matches(&glob[1..], &text[1..]),
+    }
+}
+
+fn class_end(glob: &[u8]) -> Option<usize> {
+    // A ']' right after the opening bracket (or its negation) is literal.
+    let start = if matches!(glob.get(1), Some(b'!' | b'^')) {
+        3
+    } else {
+        2
+    };
+    (start..glob.len()).find(|&i| glob[i] == b']')
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    let (negated, class) = match class.first() {
+        Some(b'!' | b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated && c != b'/'
+}
+
+// How well `candidate` matches `query` as a subsequence, higher is better, or
+// None if it doesn't. Matches at the start of words and runs of consecutive
+// matches score higher, gaps cost a little. Case-insensitive unless the query
+// has capitals in it.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    const MATCH: i64 = 16;
+    const CONSECUTIVE: i64 = 4;
+    const GAP_START: i64 = 3;
+
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    let original: Vec<char> = candidate.chars().collect();
+    let chars: Vec<char> = original.iter().copied().map(fold).collect();
+
+    // Cheap rejection before the real work.
+    let mut remaining = query.iter().peekable();
+    for c in chars.iter() {
+        remaining.next_if(|q| *q == c);
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    let name_start = original
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |i| i + 1);
+    let bonus = |j: usize, first: bool| -> i64 {
+        let boundary = match j.checked_sub(1).map(|i| original[i]) {
+            None => 8,
+            Some('/') => 10,
+            Some('_' | '-' | '.' | ' ') => 8,
+            Some(prev) if prev.is_lowercase() && original[j].is_uppercase() => 6,
+            _ => 0,
+        };
+        // Where the match starts matters most.
+        let boundary = if first { boundary * 2 } else { boundary };
+        // Matching the name counts more than matching the directories.
+        let in_name = if j >= name_start { 2 } else { 0 };
+        MATCH + boundary + in_name
+    };
+
+    // best[j]: the best score with the current query character matched at j.
+    let mut best: Vec<Option<i64>> = chars
+        .iter()
+        .enumerate()
+        .map(|(j, c)| (*c == query[0]).then(|| bonus(j, true)))
+        .collect();
+    for q in query[1..].iter() {
+        let mut next = vec![None; chars.len()];
+        // The best previous match at least two back, less what the gap costs:
+        // GAP_START and one for every character after the first.
+        let mut gapped: Option<i64> = None;
+        for j in 1..chars.len() {
+            if j >= 2
+                && let Some(prev) = best[j - 2]
+            {
+                let skipped_one = prev - GAP_START;
+                gapped = Some(gapped.map_or(skipped_one, |g| (g - 1).max(skipped_one)));
+            } else if let Some(g) = gapped {
+                gapped = Some(g - 1);
+            }
+            if chars[j] != *q {
+                continue;
+            }
+            let adjacent = best[j - 1].map(|prev| prev + CONSECUTIVE);
+            next[j] = adjacent.max(gapped).map(|s| s + bonus(j, false));
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time;
+
+    #[test]
+    fn scores_favour_word_starts_and_runs() {
+        assert_eq!(score("xyz", "src/main.rs"), None);
+        assert!(score("mn", "src/main.rs").is_some());
+        assert!(score("main", "src/main.rs") > score("main", "src/my_animation.rs"));
+        assert!(score("dv", "src/directory_view.rs") > score("dv", "src/advice.rs"));
+        assert!(score("ui", "src/ui.rs") > score("ui", "src/ui/build.rs"));
+        assert_eq!(score("Main", "src/main.rs"), None);
+    }
+
+    #[test]
+    fn gitignore_patterns_match_like_git() {
+        let base = path::PathBuf::from("/repo");
+        let ignore = Ignore::parse(
+            base.clone(),
+            "# comment\n*.log\n/target\nbuild/\ndocs/**/*.tmp\n!keep.log\n",
+        );
+        let check = |p: &str, is_dir: bool| ignore.matches(&base.join(p), is_dir);
+        assert_eq!(check("a.log", false), Some(true));
+        assert_eq!(check("deep/b.log", false), Some(true));
+        assert_eq!(check("keep.log", false), Some(false));
+        assert_eq!(check("target", true), Some(true));
+        assert_eq!(check("src/target", true), None);
+        assert_eq!(check("build", false), None);
+        assert_eq!(check("x/build", true), Some(true));
+        assert_eq!(check("docs/a/b/c.tmp", false), Some(true));
+        assert_eq!(check("docs/c.tmp", false), Some(true));
+        assert_eq!(check("main.rs", false), None);
+        assert!(glob_matches(b"file[0-9].[!c]", b"file7.h"));
+        assert!(!glob_matches(b"file[0-9].[!c]", b"file7.c"));
+    }
+
+    #[test]
+    fn finder_walks_in_the_background() {
+        let dir = env::temp_dir().join(format!("files_please-finder-{}", uuidv7::create()));
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.join("src/nested/finder.rs"), b"").unwrap();
+        fs::write(dir.join("target/finder.o"), b"").unwrap();
+
+        let mut finder = Finder::new(dir.clone(), true);
+        let started = time::Instant::now();
+        while finder.walking() && started.elapsed() < time::Duration::from_secs(5) {
+            finder.poll();
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        finder.edit_query(|query| query.push_str("finder"));
+        while finder.ranking() {
+            finder.poll();
+        }
+        let matches: Vec<&path::Path> = finder.matches().collect();
+        assert_eq!(matches, [path::Path::new("src/nested/finder.rs")]);
+
+        // Narrowed down from the matches, and widened again from everything.
+        finder.edit_query(|query| query.push('x'));
+        assert_eq!(finder.match_count(), 0);
+        finder.edit_query(|query| {
+            query.pop();
+            query.pop();
+        });
+        while finder.ranking() {
+            finder.poll();
+        }
+        let matches: Vec<&path::Path> = finder.matches().collect();
+        assert_eq!(matches, [path::Path::new("src/nested/finder.rs")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
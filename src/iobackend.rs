@@ -0,0 +1,683 @@
+use std::cmp;
+use std::ffi;
+use std::fs;
+use std::io;
+use std::io::{Seek, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path;
+
+use io_uring::{opcode, squeue, types};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+const RING_ENTRIES: u32 = 128;
+
+// A file small enough to be copied in one go, without checkpoints.
+#[derive(Clone)]
+pub struct SmallFile {
+    pub src: path::PathBuf,
+    pub dst: path::PathBuf,
+    pub len: u64,
+    pub mode: u32,
+}
+
+pub trait Backend {
+    fn name(&self) -> &'static str;
+
+    // Copies up to `max_len` bytes from `src` to `dst`, starting at `offset` in
+    // both. Returns how many bytes were copied, 0 once `src` is exhausted.
+    fn copy_range(
+        &mut self,
+        src: &fs::File,
+        dst: &fs::File,
+        offset: u64,
+        max_len: u64,
+    ) -> io::Result<u64>;
+
+    // Copies whole files and fsyncs each destination. Returns one result per
+    // file, in order.
+    fn copy_small_files(&mut self, files: &[SmallFile]) -> Vec<io::Result<u64>>;
+}
+
+// io_uring when the kernel (and any seccomp policy) allows it, blocking std::fs
+// otherwise.
+pub fn detect() -> Box<dyn Backend> {
+    match UringBackend::new() {
+        Ok(backend) => Box::new(backend),
+        Err(err) => {
+            eprintln!("io_uring unavailable, using blocking IO err={}", err);
+            Box::new(StdBackend::new())
+        }
+    }
+}
+
+pub struct StdBackend {
+    buf: Vec<u8>,
+}
+
+impl StdBackend {
+    pub fn new() -> StdBackend {
+        StdBackend {
+            buf: vec![0; CHUNK_SIZE],
+        }
+    }
+}
+
+impl Backend for StdBackend {
+    fn name(&self) -> &'static str {
+        "std"
+    }
+
+    fn copy_range(
+        &mut self,
+        src: &fs::File,
+        dst: &fs::File,
+        offset: u64,
+        max_len: u64,
+    ) -> io::Result<u64> {
+        let mut copied = 0;
+        while copied < max_len {
+            let want = cmp::min(self.buf.len() as u64, max_len - copied) as usize;
+            let n = match src.read_at(&mut self.buf[..want], offset + copied) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            dst.write_all_at(&self.buf[..n], offset + copied)?;
+            copied += n as u64;
+        }
+        Ok(copied)
+    }
+
+    fn copy_small_files(&mut self, files: &[SmallFile]) -> Vec<io::Result<u64>> {
+        files
+            .iter()
+            .map(|file| {
+                let data = fs::read(&file.src)?;
+                let mut dst = fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(file.mode)
+                    .open(&file.dst)?;
+                dst.write_all(&data)?;
+                dst.sync_all()?;
+                Ok(data.len() as u64)
+            })
+            .collect()
+    }
+}
+
+pub struct UringBackend {
+    ring: io_uring::IoUring,
+    bufs: [Vec<u8>; 2],
+}
+
+fn os_error(result: i32) -> io::Error {
+    io::Error::from_raw_os_error(-result)
+}
+
+fn c_path(p: &path::Path) -> io::Result<ffi::CString> {
+    ffi::CString::new(p.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+impl UringBackend {
+    pub fn new() -> io::Result<UringBackend> {
+        let ring = io_uring::IoUring::new(RING_ENTRIES)?;
+
+        let mut probe = io_uring::Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        for code in [
+            opcode::OpenAt::CODE,
+            opcode::Read::CODE,
+            opcode::Write::CODE,
+            opcode::Fsync::CODE,
+            opcode::Close::CODE,
+        ] {
+            if !probe.is_supported(code) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("io_uring opcode {} not supported", code),
+                ));
+            }
+        }
+
+        Ok(UringBackend {
+            ring,
+            bufs: [vec![0; CHUNK_SIZE], vec![0; CHUNK_SIZE]],
+        })
+    }
+
+    // Submits `entries` and waits for all of them. Returns each entry's result
+    // (negative errno on failure), in order. Whatever the entries point at must
+    // stay alive until this returns.
+    fn run(&mut self, entries: &[squeue::Entry]) -> io::Result<Vec<i32>> {
+        let mut results = vec![0; entries.len()];
+        for (chunk_idx, chunk) in entries.chunks(RING_ENTRIES as usize).enumerate() {
+            let base = chunk_idx * RING_ENTRIES as usize;
+            for (i, entry) in chunk.iter().enumerate() {
+                let entry = entry.clone().user_data((base + i) as u64);
+                unsafe { self.ring.submission().push(&entry) }
+                    .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+            }
+
+            let mut completed = 0;
+            while completed < chunk.len() {
+                match self.ring.submit_and_wait(chunk.len() - completed) {
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
+                for cqe in self.ring.completion() {
+                    results[cqe.user_data() as usize] = cqe.result();
+                    completed += 1;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn close_all(&mut self, fds: &[RawFd]) {
+        let entries: Vec<squeue::Entry> = fds
+            .iter()
+            .filter(|fd| **fd >= 0)
+            .map(|fd| opcode::Close::new(types::Fd(*fd)).build())
+            .collect();
+        if self.run(&entries).is_err() {
+            for fd in fds.iter().filter(|fd| **fd >= 0) {
+                unsafe { libc::close(*fd) };
+            }
+        }
+    }
+
+    // Writes every buffer in full, or reads every file to its end, resubmitting
+    // short transfers. Reads grow the buffers if a file grew since it was
+    // stat'ed and shrink them if it shrank. Files that fail get their error
+    // recorded and drop out.
+    fn transfer_all(
+        &mut self,
+        fds: &[RawFd],
+        bufs: &mut [Vec<u8>],
+        errors: &mut [Option<io::Error>],
+        write: bool,
+    ) -> io::Result<()> {
+        let mut done = vec![0usize; bufs.len()];
+        let mut eof = vec![false; bufs.len()];
+        loop {
+            let active: Vec<usize> = (0..bufs.len())
+                .filter(|i| {
+                    errors[*i].is_none()
+                        && if write {
+                            done[*i] < bufs[*i].len()
+                        } else {
+                            !eof[*i]
+                        }
+                })
+                .collect();
+            if active.is_empty() {
+                return Ok(());
+            }
+            // A full buffer is only the end of the file once a read says so.
+            for i in &active {
+                let len = bufs[*i].len();
+                if !write && done[*i] == len {
+                    bufs[*i].resize(len + len.max(4096), 0);
+                }
+            }
+
+            let entries: Vec<squeue::Entry> = active
+                .iter()
+                .map(|i| {
+                    let remaining = &mut bufs[*i][done[*i]..];
+                    let len = remaining.len() as u32;
+                    if write {
+                        opcode::Write::new(types::Fd(fds[*i]), remaining.as_ptr(), len)
+                            .offset(done[*i] as u64)
+                            .build()
+                    } else {
+                        opcode::Read::new(types::Fd(fds[*i]), remaining.as_mut_ptr(), len)
+                            .offset(done[*i] as u64)
+                            .build()
+                    }
+                })
+                .collect();
+            let results = self.run(&entries)?;
+
+            for (i, result) in active.into_iter().zip(results) {
+                if result < 0 {
+                    errors[i] = Some(os_error(result));
+                } else if result == 0 {
+                    if write {
+                        errors[i] = Some(io::Error::from(io::ErrorKind::WriteZero));
+                    } else {
+                        bufs[i].truncate(done[i]);
+                        eof[i] = true;
+                    }
+                } else {
+                    done[i] += result as usize;
+                }
+            }
+        }
+    }
+
+    fn copy_small_batch(&mut self, files: &[SmallFile]) -> io::Result<Vec<io::Result<u64>>> {
+        let mut errors: Vec<Option<io::Error>> = files.iter().map(|_| None).collect();
+        let paths = files
+            .iter()
+            .map(|file| Ok((c_path(&file.src)?, c_path(&file.dst)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut entries = Vec::with_capacity(files.len() * 2);
+        for (file, (src, dst)) in files.iter().zip(paths.iter()) {
+            entries.push(
+                opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), src.as_ptr())
+                    .flags(libc::O_RDONLY | libc::O_CLOEXEC)
+                    .build(),
+            );
+            entries.push(
+                opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), dst.as_ptr())
+                    .flags(libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC)
+                    .mode(file.mode)
+                    .build(),
+            );
+        }
+        let opened = self.run(&entries)?;
+
+        let mut src_fds = vec![-1; files.len()];
+        let mut dst_fds = vec![-1; files.len()];
+        for i in 0..files.len() {
+            src_fds[i] = opened[2 * i];
+            dst_fds[i] = opened[2 * i + 1];
+            if let Some(result) = [src_fds[i], dst_fds[i]].into_iter().find(|fd| *fd < 0) {
+                errors[i] = Some(os_error(result));
+            }
+        }
+
+        let mut bufs: Vec<Vec<u8>> = files.iter().map(|f| vec![0; f.len as usize]).collect();
+        let transferred = self
+            .transfer_all(&src_fds, &mut bufs, &mut errors, false)
+            .and_then(|()| self.transfer_all(&dst_fds, &mut bufs, &mut errors, true))
+            .and_then(|()| {
+                let synced: Vec<usize> =
+                    (0..files.len()).filter(|i| errors[*i].is_none()).collect();
+                let entries: Vec<squeue::Entry> = synced
+                    .iter()
+                    .map(|i| opcode::Fsync::new(types::Fd(dst_fds[*i])).build())
+                    .collect();
+                for (i, result) in synced.into_iter().zip(self.run(&entries)?) {
+                    if result < 0 {
+                        errors[i] = Some(os_error(result));
+                    }
+                }
+                Ok(())
+            });
+
+        self.close_all(&src_fds);
+        self.close_all(&dst_fds);
+        transferred?;
+
+        Ok(errors
+            .into_iter()
+            .zip(bufs.iter())
+            .map(|(error, buf)| match error {
+                Some(err) => Err(err),
+                None => Ok(buf.len() as u64),
+            })
+            .collect())
+    }
+}
+
+impl Backend for UringBackend {
+    fn name(&self) -> &'static str {
+        "io_uring"
+    }
+
+    // Double buffered: the next chunk is read while the previous one is written.
+    fn copy_range(
+        &mut self,
+        src: &fs::File,
+        dst: &fs::File,
+        offset: u64,
+        max_len: u64,
+    ) -> io::Result<u64> {
+        let src_fd = types::Fd(src.as_raw_fd());
+        let dst_fd = types::Fd(dst.as_raw_fd());
+        let end = offset + max_len;
+        let mut read_offset = offset;
+        let mut eof = false;
+        let mut current = 0;
+        // (buffer, len, offset) read but not yet written.
+        let mut pending: Option<(usize, usize, u64)> = None;
+
+        loop {
+            let want = if eof {
+                0
+            } else {
+                cmp::min(CHUNK_SIZE as u64, end - read_offset) as u32
+            };
+            let mut entries = vec![];
+            if want > 0 {
+                entries.push(
+                    opcode::Read::new(src_fd, self.bufs[current].as_mut_ptr(), want)
+                        .offset(read_offset)
+                        .build(),
+                );
+            }
+            if let Some((buf, len, write_offset)) = pending {
+                entries.push(
+                    opcode::Write::new(dst_fd, self.bufs[buf].as_ptr(), len as u32)
+                        .offset(write_offset)
+                        .build(),
+                );
+            }
+            if entries.is_empty() {
+                break;
+            }
+
+            let mut results = self.run(&entries)?.into_iter();
+            let mut next = None;
+            if want > 0 {
+                let read = results.next().unwrap_or(0);
+                if read < 0 {
+                    return Err(os_error(read));
+                } else if read == 0 {
+                    eof = true;
+                } else {
+                    next = Some((current, read as usize, read_offset));
+                    read_offset += read as u64;
+                }
+            }
+            if let Some((buf, len, write_offset)) = pending {
+                let written = results.next().unwrap_or(0);
+                if written < 0 {
+                    return Err(os_error(written));
+                }
+                let written = written as usize;
+                if written < len {
+                    dst.write_all_at(&self.bufs[buf][written..len], write_offset + written as u64)?;
+                }
+            }
+
+            pending = next;
+            current ^= 1;
+        }
+
+        Ok(read_offset - offset)
+    }
+
+    fn copy_small_files(&mut self, files: &[SmallFile]) -> Vec<io::Result<u64>> {
+        match self.copy_small_batch(files) {
+            Ok(results) => results,
+            Err(err) => files
+                .iter()
+                .map(|_| Err(io::Error::new(err.kind(), err.to_string())))
+                .collect(),
+        }
+    }
+}
+
+// How a file's bytes got to its destination, fastest first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    // The copy shares the source's extents (btrfs, XFS); nothing is copied.
+    Reflink,
+    CopyFileRange,
+    Sendfile,
+    // Through the IO backend.
+    ReadWrite,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Reflink => "reflink",
+            Strategy::CopyFileRange => "copy_file_range",
+            Strategy::Sendfile => "sendfile",
+            Strategy::ReadWrite => "read/write",
+        }
+    }
+}
+
+// Whether `err` means the kernel or filesystem can't copy this way, rather than
+// the copy itself going wrong.
+fn unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ENOSYS | libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOTTY)
+    )
+}
+
+// Makes `dst` share all of `src`'s data. Returns false if the filesystem can't,
+// e.g. across filesystems or on ext4.
+pub fn reflink(src: &fs::File, dst: &fs::File) -> io::Result<bool> {
+    let result = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if result == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if unsupported(&err) {
+        return Ok(false);
+    }
+    Err(err)
+}
+
+// The next stretch of data in `file` at or after `offset`, as (start, end).
+// None once only a hole, or nothing, is left. Filesystems that don't keep
+// track of holes report the whole file as data.
+pub fn next_data(file: &fs::File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+    let fd = file.as_raw_fd();
+    let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+    if start < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENXIO) {
+            return Ok(None);
+        }
+        return Err(err);
+    }
+    let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+    if end < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some((start as u64, end as u64)))
+}
+
+// Copies like `Backend::copy_range`, in the kernel where possible. Starts with
+// `strategy` and moves it on to slower ones as they turn out not to be
+// supported, so later calls for the same file go straight to one that works.
+pub fn copy_range_with(
+    strategy: &mut Strategy,
+    backend: &mut dyn Backend,
+    src: &fs::File,
+    dst: &fs::File,
+    offset: u64,
+    max_len: u64,
+) -> io::Result<u64> {
+    loop {
+        let (result, fallback) = match *strategy {
+            Strategy::Reflink | Strategy::CopyFileRange => (
+                copy_file_range(src, dst, offset, max_len),
+                Strategy::Sendfile,
+            ),
+            Strategy::Sendfile => (sendfile(src, dst, offset, max_len), Strategy::ReadWrite),
+            Strategy::ReadWrite => return backend.copy_range(src, dst, offset, max_len),
+        };
+        match result {
+            Err(err) if unsupported(&err) => *strategy = fallback,
+            result => return result,
+        }
+    }
+}
+
+fn copy_file_range(src: &fs::File, dst: &fs::File, offset: u64, max_len: u64) -> io::Result<u64> {
+    let mut copied = 0;
+    while copied < max_len {
+        let mut src_offset = (offset + copied) as libc::loff_t;
+        let mut dst_offset = src_offset;
+        let want = cmp::min(max_len - copied, isize::MAX as u64) as usize;
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                &mut src_offset,
+                dst.as_raw_fd(),
+                &mut dst_offset,
+                want,
+                0,
+            )
+        };
+        match n {
+            0 => break,
+            n if n > 0 => copied += n as u64,
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(copied)
+}
+
+fn sendfile(src: &fs::File, dst: &fs::File, offset: u64, max_len: u64) -> io::Result<u64> {
+    // sendfile writes at the destination's file position.
+    (&*dst).seek(io::SeekFrom::Start(offset))?;
+    let mut copied = 0;
+    while copied < max_len {
+        let mut src_offset = (offset + copied) as libc::off_t;
+        let want = cmp::min(max_len - copied, isize::MAX as u64) as usize;
+        let n = unsafe { libc::sendfile(dst.as_raw_fd(), src.as_raw_fd(), &mut src_offset, want) };
+        match n {
+            0 => break,
+            n if n > 0 => copied += n as u64,
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn backends() -> Vec<Box<dyn Backend>> {
+        let mut backends: Vec<Box<dyn Backend>> = vec![Box::new(StdBackend::new())];
+        if let Ok(backend) = UringBackend::new() {
+            backends.push(Box::new(backend));
+        }
+        backends
+    }
+
+    #[test]
+    fn backends_copy_ranges_and_small_files() {
+        let dir = env::temp_dir().join(format!("files_please-io-{}", uuidv7::create()));
+        fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..(3 * CHUNK_SIZE + 17))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let src_path = dir.join("big");
+        fs::write(&src_path, &data).unwrap();
+        fs::write(dir.join("grown"), &data[..data.len() / 100]).unwrap();
+
+        for mut backend in backends() {
+            let dst_path = dir.join(format!("big.{}", backend.name()));
+            let src = fs::File::open(&src_path).unwrap();
+            let dst = fs::File::create(&dst_path).unwrap();
+
+            let first = backend
+                .copy_range(&src, &dst, 0, CHUNK_SIZE as u64 + 5)
+                .unwrap();
+            assert_eq!(first, CHUNK_SIZE as u64 + 5);
+            let rest = backend.copy_range(&src, &dst, first, u64::MAX / 2).unwrap();
+            assert_eq!(first + rest, data.len() as u64);
+            assert_eq!(backend.copy_range(&src, &dst, first + rest, 10).unwrap(), 0);
+            assert_eq!(fs::read(&dst_path).unwrap(), data);
+
+            let files: Vec<SmallFile> = (0..3)
+                .map(|i| {
+                    let src = dir.join(format!("small{}", i));
+                    fs::write(&src, vec![i as u8; i * 100]).unwrap();
+                    SmallFile {
+                        src,
+                        dst: dir.join(format!("small{}.{}", i, backend.name())),
+                        len: (i * 100) as u64,
+                        mode: 0o644,
+                    }
+                })
+                .chain([SmallFile {
+                    src: dir.join("grown"),
+                    dst: dir.join(format!("grown.{}", backend.name())),
+                    len: 5,
+                    mode: 0o644,
+                }])
+                .chain([SmallFile {
+                    src: dir.join("missing"),
+                    dst: dir.join(format!("missing.{}", backend.name())),
+                    len: 1,
+                    mode: 0o644,
+                }])
+                .collect();
+            let results = backend.copy_small_files(&files);
+            for (file, result) in files.iter().zip(results.iter()).take(3) {
+                assert_eq!(*result.as_ref().unwrap(), file.len);
+                assert_eq!(fs::read(&file.dst).unwrap(), fs::read(&file.src).unwrap());
+            }
+            // Copied as it is now, not as it was when it was stat'ed.
+            assert_eq!(*results[3].as_ref().unwrap(), data.len() as u64 / 100);
+            assert_eq!(
+                fs::read(&files[3].dst).unwrap(),
+                fs::read(&files[3].src).unwrap()
+            );
+            assert_eq!(
+                results[4].as_ref().unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn kernel_copies_fall_back_to_the_backend() {
+        let dir = env::temp_dir().join(format!("files_please-kcopy-{}", uuidv7::create()));
+        fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..(2 * CHUNK_SIZE + 3)).map(|i| (i % 253) as u8).collect();
+        let src_path = dir.join("src");
+        fs::write(&src_path, &data).unwrap();
+        let src = fs::File::open(&src_path).unwrap();
+        let mut backend = StdBackend::new();
+
+        for start in [
+            Strategy::CopyFileRange,
+            Strategy::Sendfile,
+            Strategy::ReadWrite,
+        ] {
+            let dst_path = dir.join(start.name().replace('/', "-"));
+            let dst = fs::File::create(&dst_path).unwrap();
+            let mut strategy = start;
+            let first = copy_range_with(&mut strategy, &mut backend, &src, &dst, 0, 1000).unwrap();
+            let rest =
+                copy_range_with(&mut strategy, &mut backend, &src, &dst, first, u64::MAX / 2)
+                    .unwrap();
+            assert_eq!(first + rest, data.len() as u64);
+            assert_eq!(fs::read(&dst_path).unwrap(), data);
+        }
+
+        // Only some filesystems can clone; the others must say so, not fail.
+        let clone_path = dir.join("clone");
+        let clone = fs::File::create(&clone_path).unwrap();
+        if reflink(&src, &clone).unwrap() {
+            assert_eq!(fs::read(&clone_path).unwrap(), data);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
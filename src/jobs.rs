@@ -1,18 +1,316 @@
-#[derive(Debug)]
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Job {
     pub id: String,
     pub params: JobParams,
 }
 
-#[derive(Debug)]
+impl Job {
+    pub fn new(params: JobParams) -> Job {
+        Job {
+            id: uuidv7::create(),
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum JobParams {
     Copy(CopyParams),
+    Move(MoveParams),
+    Delete(DeleteParams),
+}
+
+// What to do when a single file in a job fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+    Abort,
+    // Leave the file behind, record the error and carry on.
+    Skip,
+    // Retry transient errors with backoff, abort once attempts run out.
+    Retry { attempts: u32 },
+}
+
+impl ErrorPolicy {
+    pub fn next(self) -> ErrorPolicy {
+        match self {
+            ErrorPolicy::Abort => ErrorPolicy::Skip,
+            ErrorPolicy::Skip => ErrorPolicy::Retry { attempts: 3 },
+            ErrorPolicy::Retry { .. } => ErrorPolicy::Abort,
+        }
+    }
+}
+
+// What to do when a file already exists where a copy or move would put it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollisionPolicy {
+    Overwrite,
+    Skip,
+    OverwriteIfNewer,
+    OverwriteIfDifferentSize,
+    // Copy next to it as "name (1).ext".
+    Rename,
+    // Ask in the UI, for one file or the rest of the job.
+    Ask,
+}
+
+impl CollisionPolicy {
+    pub fn next(self) -> CollisionPolicy {
+        match self {
+            CollisionPolicy::Overwrite => CollisionPolicy::Skip,
+            CollisionPolicy::Skip => CollisionPolicy::OverwriteIfNewer,
+            CollisionPolicy::OverwriteIfNewer => CollisionPolicy::OverwriteIfDifferentSize,
+            CollisionPolicy::OverwriteIfDifferentSize => CollisionPolicy::Rename,
+            CollisionPolicy::Rename => CollisionPolicy::Ask,
+            CollisionPolicy::Ask => CollisionPolicy::Overwrite,
+        }
+    }
+
+    // What to do about `src` given what is at its destination. None if the
+    // user has to be asked.
+    pub fn resolve(self, src: &fs::Metadata, dst: &fs::Metadata) -> Option<Collision> {
+        let overwrite_if = |overwrite: bool| {
+            Some(if overwrite {
+                Collision::Overwrite
+            } else {
+                Collision::Skip
+            })
+        };
+        match self {
+            CollisionPolicy::Overwrite => Some(Collision::Overwrite),
+            CollisionPolicy::Skip => Some(Collision::Skip),
+            CollisionPolicy::OverwriteIfNewer => match (src.modified(), dst.modified()) {
+                (Ok(src_mtime), Ok(dst_mtime)) => overwrite_if(src_mtime > dst_mtime),
+                _ => Some(Collision::Skip),
+            },
+            CollisionPolicy::OverwriteIfDifferentSize => overwrite_if(src.len() != dst.len()),
+            CollisionPolicy::Rename => Some(Collision::Rename),
+            CollisionPolicy::Ask => None,
+        }
+    }
+}
+
+// What a copy does with the symlinks it comes across.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymlinkPolicy {
+    // Make a link pointing wherever the original points.
+    CopyLink,
+    // Copy what the link points to.
+    Dereference,
+    // Dereference links pointing outside the tree being copied, so the copy
+    // doesn't depend on them; links within it stay links.
+    DereferenceOutside,
+}
+
+impl SymlinkPolicy {
+    pub fn next(self) -> SymlinkPolicy {
+        match self {
+            SymlinkPolicy::CopyLink => SymlinkPolicy::Dereference,
+            SymlinkPolicy::Dereference => SymlinkPolicy::DereferenceOutside,
+            SymlinkPolicy::DereferenceOutside => SymlinkPolicy::CopyLink,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collision {
+    Overwrite,
+    Skip,
+    Rename,
+}
+
+// The first "name (n).ext" next to `dst` that doesn't exist yet.
+pub fn free_name(dst: &path::Path) -> path::PathBuf {
+    let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = dst
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dst.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileError {
+    pub path: path::PathBuf,
+    pub error: String,
+}
+
+// Which attributes of the source a copy takes along besides its contents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preserve {
+    pub mode: bool,
+    // Only works as root or, for the group, with one the user is in.
+    pub ownership: bool,
+    pub timestamps: bool,
+    pub xattrs: bool,
+    pub acls: bool,
+}
+
+impl Preserve {
+    // The permission bits, like a plain `cp`.
+    pub fn basic() -> Preserve {
+        Preserve {
+            mode: true,
+            ownership: false,
+            timestamps: false,
+            xattrs: false,
+            acls: false,
+        }
+    }
+
+    // Everything, like `cp -a`.
+    pub fn archive() -> Preserve {
+        Preserve {
+            mode: true,
+            ownership: true,
+            timestamps: true,
+            xattrs: true,
+            acls: true,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CopyParams {
-    pub src: std::path::PathBuf,
-    pub dst: std::path::PathBuf,
+    pub src: path::PathBuf,
+    pub dst: path::PathBuf,
+    pub error_policy: ErrorPolicy,
+    pub collision_policy: CollisionPolicy,
+    pub symlink_policy: SymlinkPolicy,
+    // Compare checksums of every file and its copy.
+    pub verify: bool,
+    pub preserve: Preserve,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveParams {
+    pub src: path::PathBuf,
+    pub dst: path::PathBuf,
+    pub error_policy: ErrorPolicy,
+    pub collision_policy: CollisionPolicy,
+    pub symlink_policy: SymlinkPolicy,
+    pub verify: bool,
+    pub preserve: Preserve,
+}
+
+// How the files of a copy or move are handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferOptions {
+    pub error_policy: ErrorPolicy,
+    pub collision_policy: CollisionPolicy,
+    pub symlink_policy: SymlinkPolicy,
+    pub verify: bool,
+    pub preserve: Preserve,
+}
+
+impl CopyParams {
+    pub fn options(&self) -> TransferOptions {
+        TransferOptions {
+            error_policy: self.error_policy,
+            collision_policy: self.collision_policy,
+            symlink_policy: self.symlink_policy,
+            verify: self.verify,
+            preserve: self.preserve,
+        }
+    }
+}
+
+impl MoveParams {
+    pub fn options(&self) -> TransferOptions {
+        TransferOptions {
+            error_policy: self.error_policy,
+            collision_policy: self.collision_policy,
+            symlink_policy: self.symlink_policy,
+            verify: self.verify,
+            preserve: self.preserve,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeleteMode {
+    Trash,
+    Permanent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteParams {
+    pub path: path::PathBuf,
+    pub mode: DeleteMode,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+// The last durable position inside a file that was being written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub src: path::PathBuf,
+    pub dst: path::PathBuf,
+    pub offset: u64,
+}
+
+// How far an interrupted job got, rebuilt from the job log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resume {
+    pub done: collections::HashSet<path::PathBuf>,
+    pub checkpoint: Option<Checkpoint>,
+    pub errors: Vec<FileError>,
+    // Where each started file is being written, which differs from the
+    // default when it was renamed to avoid a collision.
+    pub targets: collections::HashMap<path::PathBuf, path::PathBuf>,
+    // Files left alone because of a collision.
+    pub kept: collections::HashSet<path::PathBuf>,
+}
+
+// Whether the first `len` bytes of `dst` match `src`, i.e. whether a partial
+// copy can safely be continued from `len`.
+pub fn verify_prefix(src: &path::Path, dst: &path::Path, len: u64) -> io::Result<bool> {
+    let mut src_file = fs::File::open(src)?.take(len);
+    let mut dst_file = fs::File::open(dst)?.take(len);
+    let mut src_buf = vec![0; 64 * 1024];
+    let mut dst_buf = vec![0; 64 * 1024];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let n = src_file.read(&mut src_buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        if dst_file.read_exact(&mut dst_buf[..n]).is_err() || src_buf[..n] != dst_buf[..n] {
+            return Ok(false);
+        }
+        remaining -= n as u64;
+    }
+
+    Ok(true)
+}
+
+// Streaming xxh3 of a whole file.
+pub fn checksum(p: &path::Path) -> io::Result<u128> {
+    let mut file = fs::File::open(p)?;
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(hasher.digest128()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -24,11 +322,34 @@ mod tests {
         let job = Job {
             id: uuidv7::create(),
             params: JobParams::Copy(CopyParams {
-                src: std::path::absolute("main.rs"),
-                dst: std::path::absolute("garbage.bin"),
+                src: path::absolute("main.rs").unwrap(),
+                dst: path::absolute("garbage.bin").unwrap(),
+                error_policy: ErrorPolicy::Abort,
+                collision_policy: CollisionPolicy::Overwrite,
+                symlink_policy: SymlinkPolicy::CopyLink,
+                verify: false,
+                preserve: Preserve::basic(),
             }),
         };
 
         println!("Job = {:?}", job);
     }
+
+    #[test]
+    fn verify_partial_copy_prefix() {
+        let dir = std::env::temp_dir().join(format!("files_please-jobs-{}", uuidv7::create()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("src");
+        let dst = dir.join("dst");
+        fs::write(&src, b"0123456789").unwrap();
+
+        fs::write(&dst, b"01234").unwrap();
+        assert!(verify_prefix(&src, &dst, 5).unwrap());
+        assert!(!verify_prefix(&src, &dst, 6).unwrap());
+
+        fs::write(&dst, b"01x34").unwrap();
+        assert!(!verify_prefix(&src, &dst, 5).unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
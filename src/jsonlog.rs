@@ -0,0 +1,259 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+use std::time;
+
+use crate::jobs;
+use crate::runner;
+
+// Past this size the log is moved aside to `<name>.1` when it is opened.
+const ROTATE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Human- and machine-readable record of what every job did, one JSON object
+// per line. Unlike the WAL it is never read back; it is written for people and
+// scripts to audit jobs with.
+pub struct JsonLog {
+    file: fs::File,
+}
+
+impl JsonLog {
+    // Next to the job WAL.
+    pub fn default_path(wal_path: &path::Path) -> path::PathBuf {
+        wal_path.with_file_name("jobs.jsonl")
+    }
+
+    pub fn open(log_path: &path::Path) -> io::Result<JsonLog> {
+        if let Some(parent) = log_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::metadata(log_path).is_ok_and(|m| m.len() > ROTATE_SIZE) {
+            let mut rotated = log_path.as_os_str().to_os_string();
+            rotated.push(".1");
+            fs::rename(log_path, rotated)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)?;
+        Ok(JsonLog { file })
+    }
+
+    pub fn write(&mut self, event: &runner::Event) -> io::Result<()> {
+        let ts_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut line = encode(event, ts_ms);
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+struct Object {
+    json: String,
+}
+
+impl Object {
+    fn new(ts_ms: u64, event: &str, job_id: &str) -> Object {
+        let mut object = Object {
+            json: String::from("{"),
+        };
+        object.u64("ts_ms", ts_ms);
+        object.str("event", event);
+        object.str("job_id", job_id);
+        object
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.json.len() > 1 {
+            self.json.push(',');
+        }
+        push_string(&mut self.json, key);
+        self.json.push(':');
+    }
+
+    fn str(&mut self, key: &str, value: &str) {
+        self.key(key);
+        push_string(&mut self.json, value);
+    }
+
+    fn path(&mut self, key: &str, value: &path::Path) {
+        self.str(key, &value.to_string_lossy());
+    }
+
+    fn u64(&mut self, key: &str, value: u64) {
+        self.key(key);
+        self.json.push_str(&value.to_string());
+    }
+
+    fn finish(mut self) -> String {
+        self.json.push('}');
+        self.json
+    }
+}
+
+fn push_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn encode(event: &runner::Event, ts_ms: u64) -> String {
+    match event {
+        runner::Event::Started { job } => {
+            let mut object = Object::new(ts_ms, "job_started", &job.id);
+            match &job.params {
+                jobs::JobParams::Copy(params) => {
+                    object.str("kind", "copy");
+                    object.path("src", &params.src);
+                    object.path("dst", &params.dst);
+                }
+                jobs::JobParams::Move(params) => {
+                    object.str("kind", "move");
+                    object.path("src", &params.src);
+                    object.path("dst", &params.dst);
+                }
+                jobs::JobParams::Delete(params) => {
+                    object.str(
+                        "kind",
+                        match params.mode {
+                            jobs::DeleteMode::Trash => "trash",
+                            jobs::DeleteMode::Permanent => "delete",
+                        },
+                    );
+                    object.path("src", &params.path);
+                }
+            }
+            object.finish()
+        }
+        runner::Event::Totals {
+            job_id,
+            files,
+            bytes,
+        } => {
+            let mut object = Object::new(ts_ms, "totals", job_id);
+            object.u64("files", *files);
+            object.u64("bytes", *bytes);
+            object.finish()
+        }
+        runner::Event::FileStarted {
+            job_id,
+            src,
+            dst,
+            offset,
+        } => {
+            let mut object = Object::new(ts_ms, "file_started", job_id);
+            object.path("src", src);
+            object.path("dst", dst);
+            object.u64("offset", *offset);
+            object.finish()
+        }
+        runner::Event::Progress {
+            job_id,
+            files_done,
+            bytes_done,
+            current,
+            strategy,
+        } => {
+            let mut object = Object::new(ts_ms, "bytes_written", job_id);
+            object.u64("files_done", *files_done);
+            object.u64("bytes_done", *bytes_done);
+            object.path("current", current);
+            object.str("strategy", strategy.name());
+            object.finish()
+        }
+        runner::Event::Retrying {
+            job_id,
+            error,
+            attempt,
+        } => {
+            let mut object = Object::new(ts_ms, "retried", job_id);
+            object.path("path", &error.path);
+            object.str("error", &error.error);
+            object.u64("attempt", *attempt as u64);
+            object.finish()
+        }
+        runner::Event::Error { job_id, error } => {
+            let mut object = Object::new(ts_ms, "error", job_id);
+            object.path("path", &error.path);
+            object.str("error", &error.error);
+            object.finish()
+        }
+        runner::Event::Skipped { job_id, error } => {
+            let mut object = Object::new(ts_ms, "skipped", job_id);
+            object.path("path", &error.path);
+            object.str("error", &error.error);
+            object.finish()
+        }
+        runner::Event::Unpreserved { job_id, error } => {
+            let mut object = Object::new(ts_ms, "unpreserved", job_id);
+            object.path("path", &error.path);
+            object.str("error", &error.error);
+            object.finish()
+        }
+        runner::Event::Kept { job_id, src, dst } => {
+            let mut object = Object::new(ts_ms, "kept", job_id);
+            object.path("src", src);
+            object.path("dst", dst);
+            object.finish()
+        }
+        runner::Event::Collision(prompt) => {
+            let mut object = Object::new(ts_ms, "collision", &prompt.job_id);
+            object.path("src", &prompt.src);
+            object.path("dst", &prompt.dst);
+            object.finish()
+        }
+        runner::Event::Finished { job_id, outcome } => {
+            let mut object = Object::new(ts_ms, "finished", job_id);
+            match outcome {
+                jobs::Outcome::Completed => object.str("outcome", "completed"),
+                jobs::Outcome::Failed(reason) => {
+                    object.str("outcome", "failed");
+                    object.str("reason", reason);
+                }
+                jobs::Outcome::Cancelled => object.str("outcome", "cancelled"),
+            }
+            object.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_encode_as_json_objects() {
+        let event = runner::Event::Skipped {
+            job_id: String::from("j1"),
+            error: jobs::FileError {
+                path: path::PathBuf::from("/tmp/a \"b\"\n"),
+                error: String::from("Permission denied\t(os error 13)"),
+            },
+        };
+        assert_eq!(
+            encode(&event, 42),
+            r#"{"ts_ms":42,"event":"skipped","job_id":"j1","path":"/tmp/a \"b\"\n","error":"Permission denied\t(os error 13)"}"#
+        );
+
+        let event = runner::Event::Finished {
+            job_id: String::from("j1"),
+            outcome: jobs::Outcome::Failed(String::from("\u{1}")),
+        };
+        assert_eq!(
+            encode(&event, 7),
+            r#"{"ts_ms":7,"event":"finished","job_id":"j1","outcome":"failed","reason":"\u0001"}"#
+        );
+    }
+}
@@ -1,15 +1,26 @@
 use std::collections;
 use std::env;
 use std::ffi;
-use std::fs;
 use std::path;
 use std::process;
 use std::thread;
 use std::time;
 
+mod attrs;
+mod copy;
 mod directory;
+mod finder;
+mod iobackend;
 mod jobs;
+mod jsonlog;
+mod mover;
+mod progress;
+mod runner;
+mod scheduler;
+mod trash;
 mod ui;
+mod wal;
+mod watcher;
 
 extern crate sdl3;
 use sdl3::event;
@@ -17,10 +28,15 @@ use sdl3::keyboard;
 
 const EXIT_CODE_OK: u8 = 0;
 const EXIT_CODE_SDL_ERROR: u8 = 1;
+const EXIT_CODE_WAL_ERROR: u8 = 3;
 
 enum InputMode {
     Browse,
     Search,
+    ResumePrompt,
+    ConfirmDelete,
+    Collision,
+    Find,
 }
 
 enum Action {
@@ -34,11 +50,60 @@ enum Action {
     Prev,
     ToggleSide,
     ToggleSelect,
+    Copy,
+    Move,
+    Delete,
+    Restore,
+    Refresh,
+    ResumeInterrupted,
+    CycleErrorPolicy,
+    CycleCollisionPolicy,
+    CycleSymlinkPolicy,
+    CycleColumns,
+    CycleSortKey,
+    ToggleDirsFirst,
+    ToggleCaseSensitive,
+    NextMatch,
+    ToggleVerify,
+    TogglePreserve,
     Search,
+    Find,
     Quit,
 }
 
 fn files_please_gui() -> Result<(), process::ExitCode> {
+    let wal_path = wal::Wal::default_path().ok_or_else(|| {
+        eprintln!("Unable to locate a state directory for the job log");
+        process::ExitCode::from(EXIT_CODE_WAL_ERROR)
+    })?;
+    let (mut job_log, records) = wal::Wal::open(&wal_path).map_err(|err| {
+        eprintln!("Job log {} err={}", wal_path.display(), err);
+        process::ExitCode::from(EXIT_CODE_WAL_ERROR)
+    })?;
+    eprintln!("job log = {}", job_log.path().display());
+
+    let mut interrupted = wal::in_flight(&records);
+    let live_records: Vec<wal::Record> = records
+        .into_iter()
+        .filter(|record| interrupted.iter().any(|(job, _)| job.id == record.job_id()))
+        .collect();
+    if let Err(err) = job_log.compact(&live_records) {
+        eprintln!("Job log compaction err={}", err);
+    }
+    // Nice to have, unlike the job log: jobs still run without it.
+    let progress_log_path = jsonlog::JsonLog::default_path(&wal_path);
+    let progress_log = match jsonlog::JsonLog::open(&progress_log_path) {
+        Ok(progress_log) => {
+            eprintln!("progress log = {}", progress_log_path.display());
+            Some(progress_log)
+        }
+        Err(err) => {
+            eprintln!("Progress log {} err={}", progress_log_path.display(), err);
+            None
+        }
+    };
+    let runner = runner::Runner::new(job_log, progress_log);
+
     let sdl_context = sdl3::init().map_err(|err| {
         eprintln!("SDL3 Init err={}", err);
         process::ExitCode::from(EXIT_CODE_SDL_ERROR)
@@ -88,6 +153,8 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
     })?;
 
     let mut input_mode = InputMode::Browse;
+    // Typed text arrives as text input events, only while searching.
+    let text_input = sdl_video.text_input();
 
     let keybinds = collections::HashMap::from([
         (keyboard::Keycode::Up, Action::Up),
@@ -100,19 +167,71 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
         (keyboard::Keycode::Right, Action::Next),
         (keyboard::Keycode::Tab, Action::ToggleSide),
         (keyboard::Keycode::Space, Action::ToggleSelect),
+        (keyboard::Keycode::F5, Action::Copy),
+        (keyboard::Keycode::F6, Action::Move),
+        (keyboard::Keycode::Delete, Action::Delete),
+        (keyboard::Keycode::R, Action::Restore),
+        (keyboard::Keycode::F2, Action::CycleErrorPolicy),
+        (keyboard::Keycode::F3, Action::CycleCollisionPolicy),
+        (keyboard::Keycode::F4, Action::ToggleVerify),
+        (keyboard::Keycode::F7, Action::TogglePreserve),
+        (keyboard::Keycode::F8, Action::CycleSymlinkPolicy),
+        (keyboard::Keycode::F9, Action::CycleColumns),
+        (keyboard::Keycode::S, Action::CycleSortKey),
+        (keyboard::Keycode::D, Action::ToggleDirsFirst),
+        (keyboard::Keycode::I, Action::ToggleCaseSensitive),
+        (keyboard::Keycode::N, Action::NextMatch),
         (keyboard::Keycode::Escape, Action::Quit),
         (keyboard::Keycode::Slash, Action::Search),
+        (keyboard::Keycode::F, Action::Find),
+        (keyboard::Keycode::J, Action::ResumeInterrupted),
     ]);
+    // Taken over while Ctrl is held, the rest work either way.
+    let ctrl_keybinds = collections::HashMap::from([(keyboard::Keycode::R, Action::Refresh)]);
+
+    let mut error_policy = jobs::ErrorPolicy::Abort;
+    let mut collision_policy = jobs::CollisionPolicy::Ask;
+    let mut symlink_policy = jobs::SymlinkPolicy::CopyLink;
+    let mut verify = false;
+    let mut preserve = jobs::Preserve::basic();
+
+    // Jobs waiting for an answer about a file that is in the way, oldest first.
+    let mut collisions: collections::VecDeque<runner::CollisionPrompt> =
+        collections::VecDeque::new();
+
+    // Waiting for confirmation before they are deleted for good.
+    let mut pending_delete: Vec<path::PathBuf> = vec![];
 
     let mut dir_path = env::current_dir().unwrap_or(path::PathBuf::from("."));
 
-    let read_dir_it = fs::read_dir(&dir_path).map_err(|err| {
-        eprintln!("Failed to read current working directory {}", err);
-        process::ExitCode::from(2)
-    })?;
-    let de = directory::Entries::new(dir_path.clone(), read_dir_it);
+    // One listing per side, as each side's view takes in its own.
+    let open_cwd = || {
+        directory::Loading::open(dir_path.clone()).map_err(|err| {
+            eprintln!("Failed to read current working directory {}", err);
+            process::ExitCode::from(2)
+        })
+    };
+    let left_loading = open_cwd()?;
+    let right_loading = open_cwd()?;
 
-    let mut gui = ui::UI::new(texture_creator, &font, de.clone(), de.clone());
+    let mut gui = ui::UI::new(texture_creator, &font, left_loading, right_loading);
+
+    for (job, resume) in interrupted.iter() {
+        gui.add_job(
+            job.clone(),
+            ui::JobStatus::Interrupted {
+                files_done: resume.done.len(),
+                offset: resume.checkpoint.as_ref().map(|c| c.offset),
+            },
+        );
+        for error in resume.errors.iter() {
+            gui.add_job_error(&job.id, error.clone());
+        }
+    }
+    if !interrupted.is_empty() {
+        gui.set_jobs_prompt(Some(resume_prompt(interrupted.len())));
+        input_mode = InputMode::ResumePrompt;
+    }
 
     loop {
         for ev in event_pump.poll_iter() {
@@ -120,13 +239,31 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
                 event::Event::Quit { .. } => return Ok(()),
                 event::Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
                     ..
                 } => match input_mode {
                     InputMode::Browse => {
-                        if let Some(action) = keybinds.get(&keycode) {
+                        let ctrl_action = keymod
+                            .intersects(keyboard::Mod::LCTRLMOD | keyboard::Mod::RCTRLMOD)
+                            .then(|| ctrl_keybinds.get(&keycode))
+                            .flatten();
+                        if let Some(action) = ctrl_action.or_else(|| keybinds.get(&keycode)) {
                             match action {
                                 Action::Quit => return Ok(()),
-                                Action::Search => input_mode = InputMode::Search,
+                                Action::Search => {
+                                    gui.start_search();
+                                    text_input.start(canvas.window());
+                                    input_mode = InputMode::Search;
+                                }
+                                Action::Find => {
+                                    gui.open_finder(true);
+                                    text_input.start(canvas.window());
+                                    input_mode = InputMode::Find;
+                                }
+                                // Shift goes back to the previous match.
+                                Action::NextMatch => gui.next_match(!keymod.intersects(
+                                    keyboard::Mod::LSHIFTMOD | keyboard::Mod::RSHIFTMOD,
+                                )),
                                 Action::Up => gui.up(1),
                                 Action::Down => gui.down(1),
                                 Action::Top => gui.top(),
@@ -135,7 +272,7 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
                                 Action::JumpDown => gui.down(10),
                                 Action::Next => {
                                     if let Some(hovered_entry) = gui.hovered_entry() {
-                                        if hovered_entry.kind == directory::EntryKind::Dir {
+                                        if hovered_entry.is_dir(&gui.active_dir_path()) {
                                             eprintln!(
                                                 "next on hovered entry {}",
                                                 hovered_entry.name.display()
@@ -143,16 +280,22 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
                                             dir_path = gui.active_dir_path();
                                             dir_path.push(hovered_entry.name);
 
-                                            if let Ok(read_dir_it) = fs::read_dir(&dir_path) {
-                                                let de = directory::Entries::new(
-                                                    dir_path.clone(),
-                                                    read_dir_it,
-                                                );
-                                                gui.update_dir_entries(de);
-                                            }
-
-                                            gui.show_dir(dir_path.clone(), path::PathBuf::from(""));
-                                        } else if hovered_entry.kind == directory::EntryKind::File {
+                                            show_dir(&mut gui, &dir_path, path::Path::new(""));
+                                        } else if let directory::EntryKind::Symlink {
+                                            target,
+                                            broken: true,
+                                        } = &hovered_entry.kind
+                                        {
+                                            eprintln!(
+                                                "Broken symlink {} -> {}",
+                                                hovered_entry.name.display(),
+                                                target.display()
+                                            );
+                                        } else if matches!(
+                                            hovered_entry.kind,
+                                            directory::EntryKind::File
+                                                | directory::EntryKind::Symlink { .. }
+                                        ) {
                                             let mut file_path = dir_path.clone();
                                             file_path.push(hovered_entry.name);
                                             let open_status = process::Command::new("xdg-open")
@@ -172,34 +315,343 @@ fn files_please_gui() -> Result<(), process::ExitCode> {
                                         dir_path.file_name().unwrap_or(ffi::OsStr::new("")),
                                     );
                                     dir_path.pop();
-                                    if let Ok(read_dir_it) = fs::read_dir(&dir_path) {
-                                        let de =
-                                            directory::Entries::new(dir_path.clone(), read_dir_it);
-                                        gui.update_dir_entries(de);
-                                    }
-                                    gui.show_dir(dir_path.clone(), from_name);
+                                    show_dir(&mut gui, &dir_path, &from_name);
                                 }
                                 Action::ToggleSide => gui.toggle_side(),
+                                Action::CycleColumns => gui.cycle_columns(),
+                                Action::CycleSortKey => {
+                                    // Shift flips the direction instead.
+                                    if keymod.intersects(
+                                        keyboard::Mod::LSHIFTMOD | keyboard::Mod::RSHIFTMOD,
+                                    ) {
+                                        gui.change_sort(|sort| sort.reverse = !sort.reverse);
+                                    } else {
+                                        gui.change_sort(|sort| sort.key = sort.key.next());
+                                    }
+                                }
+                                Action::ToggleDirsFirst => {
+                                    gui.change_sort(|sort| sort.dirs_first = !sort.dirs_first)
+                                }
+                                Action::ToggleCaseSensitive => gui
+                                    .change_sort(|sort| sort.case_sensitive = !sort.case_sensitive),
                                 Action::ToggleSelect => gui.toggle_select(),
+                                Action::Copy | Action::Move => {
+                                    let dst = gui.inactive_dir_path();
+                                    for src in gui.selected_paths() {
+                                        let dst = dst.clone();
+                                        let params = match action {
+                                            Action::Move => {
+                                                jobs::JobParams::Move(jobs::MoveParams {
+                                                    src,
+                                                    dst,
+                                                    error_policy,
+                                                    collision_policy,
+                                                    symlink_policy,
+                                                    verify,
+                                                    preserve,
+                                                })
+                                            }
+                                            _ => jobs::JobParams::Copy(jobs::CopyParams {
+                                                src,
+                                                dst,
+                                                error_policy,
+                                                collision_policy,
+                                                symlink_policy,
+                                                verify,
+                                                preserve,
+                                            }),
+                                        };
+                                        let job = jobs::Job::new(params);
+                                        match runner.submit(job) {
+                                            Ok(job) => gui.add_job(job, ui::JobStatus::Queued),
+                                            Err(err) => eprintln!("Job log append err={}", err),
+                                        }
+                                    }
+                                }
+                                Action::Delete => {
+                                    let paths = gui.selected_paths();
+                                    if keymod.intersects(
+                                        keyboard::Mod::LSHIFTMOD | keyboard::Mod::RSHIFTMOD,
+                                    ) {
+                                        if !paths.is_empty() {
+                                            gui.set_jobs_prompt(Some(format!(
+                                                "Permanently delete {} item(s)? [y/n]",
+                                                paths.len()
+                                            )));
+                                            pending_delete = paths;
+                                            input_mode = InputMode::ConfirmDelete;
+                                        }
+                                    } else {
+                                        for path in paths {
+                                            let job = jobs::Job::new(jobs::JobParams::Delete(
+                                                jobs::DeleteParams {
+                                                    path,
+                                                    mode: jobs::DeleteMode::Trash,
+                                                },
+                                            ));
+                                            match runner.submit(job) {
+                                                Ok(job) => gui.add_job(job, ui::JobStatus::Queued),
+                                                Err(err) => eprintln!("Job log append err={}", err),
+                                            }
+                                        }
+                                    }
+                                }
+                                Action::CycleErrorPolicy => {
+                                    error_policy = error_policy.next();
+                                    gui.set_error_policy(error_policy);
+                                }
+                                Action::CycleCollisionPolicy => {
+                                    collision_policy = collision_policy.next();
+                                    gui.set_collision_policy(collision_policy);
+                                }
+                                Action::CycleSymlinkPolicy => {
+                                    symlink_policy = symlink_policy.next();
+                                    gui.set_symlink_policy(symlink_policy);
+                                }
+                                Action::ToggleVerify => {
+                                    verify = !verify;
+                                    gui.set_verify(verify);
+                                }
+                                Action::TogglePreserve => {
+                                    preserve = if preserve == jobs::Preserve::basic() {
+                                        jobs::Preserve::archive()
+                                    } else {
+                                        jobs::Preserve::basic()
+                                    };
+                                    gui.set_preserve(preserve);
+                                }
+                                // Only in a trash, elsewhere there is nothing
+                                // to restore.
+                                Action::Restore if trash::is_files_dir(&gui.active_dir_path()) => {
+                                    for path in gui.selected_paths() {
+                                        match trash::restore(&path) {
+                                            Ok(original) => eprintln!(
+                                                "Restored {} to {}",
+                                                path.display(),
+                                                original.display()
+                                            ),
+                                            Err(err) => {
+                                                eprintln!("Restore {} err={}", path.display(), err)
+                                            }
+                                        }
+                                    }
+                                }
+                                Action::Restore => {}
+                                Action::Refresh => gui.refresh(),
+                                // Asks again about the jobs the prompt was
+                                // dismissed for.
+                                Action::ResumeInterrupted if !interrupted.is_empty() => {
+                                    gui.set_jobs_prompt(Some(resume_prompt(interrupted.len())));
+                                    input_mode = InputMode::ResumePrompt;
+                                }
+                                Action::ResumeInterrupted => {}
+                            }
+                        }
+                    }
+                    InputMode::ResumePrompt => match keycode {
+                        keyboard::Keycode::Y => {
+                            for (job, resume) in interrupted.drain(..) {
+                                gui.set_job_status(&job.id, ui::JobStatus::Queued);
+                                runner.resume(job, resume);
+                            }
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
+                        }
+                        keyboard::Keycode::N => {
+                            for (job, _) in interrupted.drain(..) {
+                                match runner.discard(&job.id) {
+                                    Ok(()) => gui.remove_job(&job.id),
+                                    Err(err) => eprintln!("Job log append err={}", err),
+                                }
                             }
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
+                        }
+                        keyboard::Keycode::Escape => {
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
+                        }
+                        _ => {}
+                    },
+                    InputMode::ConfirmDelete => match keycode {
+                        keyboard::Keycode::Y => {
+                            for path in pending_delete.drain(..) {
+                                let job =
+                                    jobs::Job::new(jobs::JobParams::Delete(jobs::DeleteParams {
+                                        path,
+                                        mode: jobs::DeleteMode::Permanent,
+                                    }));
+                                match runner.submit(job) {
+                                    Ok(job) => gui.add_job(job, ui::JobStatus::Queued),
+                                    Err(err) => eprintln!("Job log append err={}", err),
+                                }
+                            }
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
+                        }
+                        keyboard::Keycode::N | keyboard::Keycode::Escape => {
+                            pending_delete.clear();
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
+                        }
+                        _ => {}
+                    },
+                    InputMode::Collision => {
+                        let policy = match keycode {
+                            keyboard::Keycode::O => Some(jobs::CollisionPolicy::Overwrite),
+                            keyboard::Keycode::S | keyboard::Keycode::Escape => {
+                                Some(jobs::CollisionPolicy::Skip)
+                            }
+                            keyboard::Keycode::N => Some(jobs::CollisionPolicy::OverwriteIfNewer),
+                            keyboard::Keycode::D => {
+                                Some(jobs::CollisionPolicy::OverwriteIfDifferentSize)
+                            }
+                            keyboard::Keycode::R => Some(jobs::CollisionPolicy::Rename),
+                            _ => None,
+                        };
+                        if let Some(policy) = policy
+                            && let Some(prompt) = collisions.pop_front()
+                        {
+                            let _ = prompt.reply.send(runner::CollisionAnswer {
+                                policy,
+                                apply_to_all: keymod.intersects(
+                                    keyboard::Mod::LSHIFTMOD | keyboard::Mod::RSHIFTMOD,
+                                ),
+                            });
+                            gui.set_jobs_prompt(None);
+                            input_mode = InputMode::Browse;
                         }
                     }
                     InputMode::Search => match keycode {
                         keyboard::Keycode::Escape => {
+                            gui.cancel_search();
+                            text_input.stop(canvas.window());
                             input_mode = InputMode::Browse;
                         }
+                        keyboard::Keycode::Return | keyboard::Keycode::KpEnter => {
+                            gui.commit_search();
+                            text_input.stop(canvas.window());
+                            input_mode = InputMode::Browse;
+                        }
+                        keyboard::Keycode::Backspace => gui.edit_search(|query| {
+                            query.pop();
+                        }),
+                        _ => {}
+                    },
+                    InputMode::Find => match keycode {
+                        keyboard::Keycode::Escape => {
+                            gui.close_finder();
+                            text_input.stop(canvas.window());
+                            input_mode = InputMode::Browse;
+                        }
+                        keyboard::Keycode::Return | keyboard::Keycode::KpEnter => {
+                            let choice = gui.finder_choice();
+                            gui.close_finder();
+                            text_input.stop(canvas.window());
+                            input_mode = InputMode::Browse;
+                            // Show the directory it is in, with the cursor on it.
+                            if let Some(choice) = choice
+                                && let (Some(parent), Some(name)) =
+                                    (choice.parent(), choice.file_name())
+                            {
+                                dir_path = parent.to_path_buf();
+                                show_dir(&mut gui, &dir_path, path::Path::new(name));
+                                gui.hover_name(path::Path::new(name));
+                            }
+                        }
+                        keyboard::Keycode::Up => gui.finder_up(),
+                        keyboard::Keycode::Down => gui.finder_down(),
+                        keyboard::Keycode::Tab => gui.toggle_finder_gitignore(),
+                        keyboard::Keycode::Backspace => gui.edit_finder_query(|query| {
+                            query.pop();
+                        }),
                         _ => {}
                     },
                 },
+                event::Event::TextInput { text, .. } => match input_mode {
+                    InputMode::Search => gui.edit_search(|query| query.push_str(&text)),
+                    InputMode::Find => gui.edit_finder_query(|query| query.push_str(&text)),
+                    _ => {}
+                },
                 _ => {}
             }
         }
 
+        for event in runner.events() {
+            match event {
+                runner::Event::Totals {
+                    job_id,
+                    files,
+                    bytes,
+                } => gui.set_job_totals(&job_id, files, bytes),
+                runner::Event::Progress {
+                    job_id,
+                    files_done,
+                    bytes_done,
+                    current,
+                    strategy,
+                } => gui.set_job_status(
+                    &job_id,
+                    ui::JobStatus::Running {
+                        files_done,
+                        bytes_done,
+                        current,
+                        strategy,
+                    },
+                ),
+                runner::Event::Skipped { job_id, error }
+                | runner::Event::Unpreserved { job_id, error } => gui.add_job_error(&job_id, error),
+                runner::Event::Collision(prompt) => collisions.push_back(prompt),
+                runner::Event::Started { .. }
+                | runner::Event::FileStarted { .. }
+                | runner::Event::Retrying { .. }
+                | runner::Event::Kept { .. }
+                | runner::Event::Error { .. } => {}
+                runner::Event::Finished { job_id, outcome } => {
+                    gui.set_job_status(&job_id, ui::JobStatus::Finished(outcome))
+                }
+            }
+        }
+
+        // Other prompts go first; the jobs asking can wait.
+        if matches!(input_mode, InputMode::Browse)
+            && let Some(prompt) = collisions.front()
+        {
+            gui.set_jobs_prompt(Some(format!(
+                "{} exists. [o]verwrite [s]kip [r]ename if [n]ewer if [d]ifferent size, Shift: all files of this job",
+                prompt.dst.display()
+            )));
+            input_mode = InputMode::Collision;
+        }
+
+        gui.poll_loading();
+        gui.poll_changes();
+        gui.poll_finder();
         gui.render(&mut canvas);
         thread::sleep(time::Duration::from_micros(2000));
     }
 }
 
+// Asks about the interrupted jobs that are still undecided.
+fn resume_prompt(interrupted: usize) -> String {
+    format!(
+        "{} interrupted job(s) found. Resume? [y/n, Esc to decide later with J]",
+        interrupted
+    )
+}
+
+// Shows `dir_path` in the active pane, starting to load it in the background
+// unless that side has it already; cached views refresh themselves.
+fn show_dir(gui: &mut ui::UI, dir_path: &path::Path, selected_entry: &path::Path) {
+    if !gui.has_dir(dir_path) {
+        match directory::Loading::open(dir_path.to_path_buf()) {
+            Ok(loading) => gui.load_dir(loading),
+            Err(err) => eprintln!("Read dir {} err={}", dir_path.display(), err),
+        }
+    }
+    gui.show_dir(dir_path.to_path_buf(), selected_entry.to_path_buf());
+}
+
 fn main() -> process::ExitCode {
     match files_please_gui() {
         Ok(()) => process::ExitCode::from(EXIT_CODE_OK),
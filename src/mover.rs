@@ -0,0 +1,100 @@
+use std::collections;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path;
+
+use crate::copy;
+
+// Whether `dst` would be created on the same device `src` lives on.
+fn same_device(src: &path::Path, dst: &path::Path) -> io::Result<bool> {
+    let src_dev = fs::symlink_metadata(src)?.dev();
+    let dst_dir = dst.parent().unwrap_or(path::Path::new("/"));
+    Ok(fs::metadata(dst_dir)?.dev() == src_dev)
+}
+
+pub fn move_path(copier: &mut copy::Copier, src: &path::Path, dst: &path::Path) -> io::Result<()> {
+    let src_metadata = match fs::symlink_metadata(src) {
+        // A previous run got as far as removing the source.
+        Err(err) if err.kind() == io::ErrorKind::NotFound && dst.exists() => return Ok(()),
+        result => result?,
+    };
+
+    if same_device(src, dst)? {
+        let target = match fs::symlink_metadata(dst) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Some(dst.to_path_buf()),
+            Err(err) => return Err(err),
+            // Merged into each other, which takes a copy.
+            Ok(dst_metadata) if src_metadata.is_dir() || dst_metadata.is_dir() => None,
+            // Replaced in one go if the collision policy says so.
+            Ok(_) => match copier.resolve(src, dst)? {
+                Some(target) => Some(target),
+                None => return Ok(()),
+            },
+        };
+        if let Some(target) = target {
+            match fs::rename(src, target) {
+                Ok(()) => return Ok(()),
+                // Same filesystem mounted twice, e.g. a bind mount.
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Sources of copies that failed verification are in `left_behind`.
+    copier.copy(src, dst)?;
+    remove_copied(copier, src, dst, &copier.left_behind())?;
+    copier.verified()
+}
+
+// Deletes everything under `src` that made it to `dst`, leaving skipped and
+// kept files (and the directories holding them) in place. Returns whether
+// `src` itself was removed.
+fn remove_copied(
+    copier: &copy::Copier,
+    src: &path::Path,
+    dst: &path::Path,
+    left_behind: &collections::HashSet<&path::Path>,
+) -> io::Result<bool> {
+    if left_behind.contains(src) {
+        return Ok(false);
+    }
+    // Renamed to avoid a collision.
+    let dst = copier.target(src).unwrap_or(dst);
+
+    let src_metadata = fs::symlink_metadata(src)?;
+    let dst_metadata = fs::symlink_metadata(dst)?;
+
+    if src_metadata.is_dir() && dst_metadata.is_dir() {
+        let mut removed_all = true;
+        for entry in fs::read_dir(src)? {
+            let name = entry?.file_name();
+            removed_all &= remove_copied(copier, &src.join(&name), &dst.join(&name), left_behind)?;
+        }
+        if removed_all {
+            fs::remove_dir(src)?;
+        }
+        Ok(removed_all)
+    } else if !src_metadata.is_dir() && !src_metadata.is_file() {
+        // A symlink, copied as a link or as what it points to (either way only
+        // the link itself goes), or a FIFO or device node.
+        fs::remove_file(src)?;
+        Ok(true)
+    } else if src_metadata.is_file()
+        && dst_metadata.is_file()
+        && src_metadata.len() == dst_metadata.len()
+    {
+        fs::remove_file(src)?;
+        Ok(true)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} does not match {} after copying",
+                dst.display(),
+                src.display()
+            ),
+        ))
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections;
+use std::time;
+
+// How far back the throughput history goes, in one second buckets.
+const HISTORY_SECS: usize = 60;
+// Window the displayed transfer rate is averaged over.
+const RATE_WINDOW_SECS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Magnitude {
+    Byte,
+    Kilo,
+    Mega,
+    Giga,
+}
+
+pub fn magnitude(bytes: u64) -> Magnitude {
+    match bytes {
+        0..1024 => Magnitude::Byte,
+        1024..1_048_576 => Magnitude::Kilo,
+        1_048_576..1_073_741_824 => Magnitude::Mega,
+        _ => Magnitude::Giga,
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let value = bytes as f64;
+    match magnitude(bytes) {
+        Magnitude::Byte => format!("{} B", bytes),
+        Magnitude::Kilo => format!("{:.1} KiB", value / 1024.0),
+        Magnitude::Mega => format!("{:.1} MiB", value / 1_048_576.0),
+        Magnitude::Giga => format!("{:.2} GiB", value / 1_073_741_824.0),
+    }
+}
+
+pub fn format_duration(duration: time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// Bytes transferred per second over the last minute of a job.
+pub struct Throughput {
+    last_bytes: u64,
+    bucket_start: time::Instant,
+    // Oldest first; the last one is the second in progress.
+    buckets: collections::VecDeque<u64>,
+}
+
+impl Throughput {
+    // `bytes_done` is the baseline: work done before this run, e.g. files
+    // finished before a resume, doesn't count towards the rate.
+    pub fn new(now: time::Instant, bytes_done: u64) -> Throughput {
+        Throughput {
+            last_bytes: bytes_done,
+            bucket_start: now,
+            buckets: collections::VecDeque::from([0]),
+        }
+    }
+
+    pub fn record(&mut self, now: time::Instant, bytes_done: u64) {
+        self.advance(now);
+        if let Some(bucket) = self.buckets.back_mut() {
+            *bucket += bytes_done.saturating_sub(self.last_bytes);
+        }
+        self.last_bytes = bytes_done;
+    }
+
+    fn advance(&mut self, now: time::Instant) {
+        let elapsed = now.saturating_duration_since(self.bucket_start).as_secs();
+        for _ in 0..elapsed.min(HISTORY_SECS as u64) {
+            self.buckets.push_back(0);
+        }
+        while self.buckets.len() > HISTORY_SECS {
+            self.buckets.pop_front();
+        }
+        self.bucket_start += time::Duration::from_secs(elapsed);
+    }
+
+    // Bytes per second, oldest first, as of `now`. Seconds without any progress
+    // show up as zeroes.
+    pub fn history(&self, now: time::Instant) -> Vec<u64> {
+        let idle = now.saturating_duration_since(self.bucket_start).as_secs() as usize;
+        let mut history: Vec<u64> = self.buckets.iter().copied().collect();
+        history.extend(std::iter::repeat_n(0, idle.min(HISTORY_SECS)));
+        let excess = history.len().saturating_sub(HISTORY_SECS);
+        history.split_off(excess)
+    }
+
+    // Bytes per second averaged over the last few complete seconds, so it
+    // settles instead of jumping with every progress event and drops off when
+    // the transfer stalls.
+    pub fn rate(&self, now: time::Instant) -> f64 {
+        let mut history = self.history(now);
+        // The newest second is still in progress.
+        history.pop();
+        let window = &history[history.len().saturating_sub(RATE_WINDOW_SECS)..];
+        if window.is_empty() {
+            return 0.0;
+        }
+        window.iter().sum::<u64>() as f64 / window.len() as f64
+    }
+}
+
+pub fn eta(bytes_remaining: u64, rate: f64) -> Option<time::Duration> {
+    if rate < 1.0 {
+        return None;
+    }
+    Some(time::Duration::from_secs_f64(bytes_remaining as f64 / rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throughput_tracks_rate_and_history() {
+        let start = time::Instant::now();
+        let at = |secs: f64| start + time::Duration::from_secs_f64(secs);
+
+        // Bytes that were already done don't count.
+        let mut throughput = Throughput::new(start, 1000);
+        for sec in 1..=10 {
+            throughput.record(at(sec as f64), 1000 + sec * 2048);
+        }
+        let rate = throughput.rate(at(10.0));
+        assert!((rate - 2048.0).abs() < 1.0, "rate {}", rate);
+        assert_eq!(eta(4096, rate).map(|d| d.as_secs_f64().round()), Some(2.0));
+
+        let history = throughput.history(at(10.5));
+        assert_eq!(history.len(), 11);
+        assert_eq!(history[1..], [2048; 10]);
+
+        // Stalled for a while: the rate falls to nothing.
+        assert_eq!(throughput.rate(at(20.0)), 0.0);
+        assert_eq!(throughput.history(at(200.0)), vec![0; HISTORY_SECS]);
+    }
+
+    #[test]
+    fn formats_sizes_and_durations() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1_048_576), "5.0 MiB");
+        assert_eq!(magnitude(3 * 1_073_741_824), Magnitude::Giga);
+        assert_eq!(format_duration(time::Duration::from_secs(75)), "1:15");
+        assert_eq!(format_duration(time::Duration::from_secs(3725)), "1:02:05");
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs;
+use std::io;
+use std::path;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use crate::copy;
+use crate::iobackend;
+use crate::jobs;
+use crate::jsonlog;
+use crate::mover;
+use crate::scheduler;
+use crate::trash;
+use crate::wal;
+
+pub enum Event {
+    Started {
+        job: jobs::Job,
+    },
+    // What a copy or move will transfer, sent before it starts.
+    Totals {
+        job_id: String,
+        files: u64,
+        bytes: u64,
+    },
+    FileStarted {
+        job_id: String,
+        src: path::PathBuf,
+        dst: path::PathBuf,
+        offset: u64,
+    },
+    Progress {
+        job_id: String,
+        files_done: u64,
+        bytes_done: u64,
+        current: path::PathBuf,
+        strategy: iobackend::Strategy,
+    },
+    Retrying {
+        job_id: String,
+        error: jobs::FileError,
+        attempt: u32,
+    },
+    // The error that ends the job.
+    Error {
+        job_id: String,
+        error: jobs::FileError,
+    },
+    Skipped {
+        job_id: String,
+        error: jobs::FileError,
+    },
+    // Attributes of a copy that could not be made to match the source.
+    Unpreserved {
+        job_id: String,
+        error: jobs::FileError,
+    },
+    // Left alone because `dst` already existed.
+    Kept {
+        job_id: String,
+        src: path::PathBuf,
+        dst: path::PathBuf,
+    },
+    Collision(CollisionPrompt),
+    Finished {
+        job_id: String,
+        outcome: jobs::Outcome,
+    },
+}
+
+// A file that already exists at its destination under the Ask policy. The job
+// waits until `reply` is answered.
+pub struct CollisionPrompt {
+    pub job_id: String,
+    pub src: path::PathBuf,
+    pub dst: path::PathBuf,
+    pub reply: mpsc::Sender<CollisionAnswer>,
+}
+
+pub struct CollisionAnswer {
+    pub policy: jobs::CollisionPolicy,
+    pub apply_to_all: bool,
+}
+
+// Executes jobs off the UI thread. The UI submits jobs and drains `events`
+// once per frame. Every event is also written to the progress log, if any.
+pub struct Runner {
+    job_log: Arc<Mutex<wal::Wal>>,
+    queue: mpsc::Sender<(jobs::Job, jobs::Resume)>,
+    events: mpsc::Receiver<Event>,
+}
+
+impl Runner {
+    pub fn new(job_log: wal::Wal, mut progress_log: Option<jsonlog::JsonLog>) -> Runner {
+        let job_log = Arc::new(Mutex::new(job_log));
+        let (queue_tx, queue_rx) = mpsc::channel::<(jobs::Job, jobs::Resume)>();
+        let (worker_events_tx, worker_events_rx) = mpsc::channel::<Event>();
+        let (events_tx, events_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for event in worker_events_rx {
+                if let Some(log) = &mut progress_log
+                    && let Err(err) = log.write(&event)
+                {
+                    eprintln!("Progress log write err={}", err);
+                }
+                let _ = events_tx.send(event);
+            }
+        });
+
+        // Jobs are enqueued in submission order and each runs on its own
+        // thread once the scheduler hands it the devices it touches.
+        let worker_log = job_log.clone();
+        thread::spawn(move || {
+            let scheduler = Arc::new(scheduler::Scheduler::default());
+            for (job, resume) in queue_rx {
+                let devices = scheduler::devices_for(&job_paths(&job));
+                let ticket = scheduler.enqueue(devices.clone());
+                let scheduler = scheduler.clone();
+                let job_log = worker_log.clone();
+                let events = worker_events_tx.clone();
+                thread::spawn(move || {
+                    let _claim = scheduler.wait(ticket);
+                    let mut backend = iobackend::detect();
+                    eprintln!(
+                        "Job#{} running devices={:?} io={}",
+                        job.id,
+                        devices,
+                        backend.name()
+                    );
+                    run(&job, &resume, &job_log, &events, backend.as_mut());
+                });
+            }
+        });
+
+        Runner {
+            job_log,
+            queue: queue_tx,
+            events: events_rx,
+        }
+    }
+
+    // Logs the job and queues it. Returns the job as it was logged, with its
+    // destination resolved.
+    pub fn submit(&self, mut job: jobs::Job) -> io::Result<jobs::Job> {
+        match &mut job.params {
+            jobs::JobParams::Copy(params) => {
+                params.dst = copy::resolve_target(&params.src, &params.dst);
+            }
+            jobs::JobParams::Move(params) => {
+                params.dst = copy::resolve_target(&params.src, &params.dst);
+            }
+            jobs::JobParams::Delete(_) => {}
+        }
+        self.job_log
+            .lock()
+            .unwrap()
+            .append(&wal::Record::Created(job.clone()))?;
+        let _ = self.queue.send((job.clone(), jobs::Resume::default()));
+        Ok(job)
+    }
+
+    // Queues a job that is already in the log.
+    pub fn resume(&self, job: jobs::Job, resume: jobs::Resume) {
+        let _ = self.queue.send((job, resume));
+    }
+
+    // Marks a logged job as never to be run.
+    pub fn discard(&self, job_id: &str) -> io::Result<()> {
+        self.job_log.lock().unwrap().append(&wal::Record::Finished {
+            job_id: job_id.to_string(),
+            outcome: jobs::Outcome::Cancelled,
+        })
+    }
+
+    pub fn events(&self) -> mpsc::TryIter<'_, Event> {
+        self.events.try_iter()
+    }
+}
+
+fn job_paths(job: &jobs::Job) -> Vec<&path::Path> {
+    match &job.params {
+        jobs::JobParams::Copy(params) => vec![&params.src, &params.dst],
+        jobs::JobParams::Move(params) => vec![&params.src, &params.dst],
+        jobs::JobParams::Delete(params) => vec![&params.path],
+    }
+}
+
+fn run(
+    job: &jobs::Job,
+    resume: &jobs::Resume,
+    job_log: &Mutex<wal::Wal>,
+    events: &mpsc::Sender<Event>,
+    backend: &mut dyn iobackend::Backend,
+) {
+    let _ = events.send(Event::Started { job: job.clone() });
+    let result = match &job.params {
+        jobs::JobParams::Copy(params) => {
+            let mut copier =
+                copy::Copier::new(&job.id, job_log, events, resume, params.options(), backend);
+            copier
+                .copy(&params.src, &params.dst)
+                .and_then(|()| copier.verified())
+        }
+        jobs::JobParams::Move(params) => mover::move_path(
+            &mut copy::Copier::new(&job.id, job_log, events, resume, params.options(), backend),
+            &params.src,
+            &params.dst,
+        ),
+        jobs::JobParams::Delete(params) => delete(params),
+    };
+
+    let outcome = match result {
+        Ok(()) => jobs::Outcome::Completed,
+        Err(err) => jobs::Outcome::Failed(err.to_string()),
+    };
+    eprintln!("Job#{} finished {:?}", job.id, outcome);
+
+    if let Err(err) = job_log.lock().unwrap().append(&wal::Record::Finished {
+        job_id: job.id.clone(),
+        outcome: outcome.clone(),
+    }) {
+        eprintln!("Job log append err={}", err);
+    }
+    let _ = events.send(Event::Finished {
+        job_id: job.id.clone(),
+        outcome,
+    });
+}
+
+fn delete(params: &jobs::DeleteParams) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(&params.path) {
+        // Already gone, e.g. a previous run finished but wasn't logged.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        result => result?,
+    };
+    match params.mode {
+        jobs::DeleteMode::Trash => trash::trash(&params.path).map(|_| ()),
+        jobs::DeleteMode::Permanent if metadata.is_dir() => fs::remove_dir_all(&params.path),
+        jobs::DeleteMode::Permanent => fs::remove_file(&params.path),
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path;
+use std::sync::{Condvar, Mutex};
+
+// Device of `p`, or of its closest existing ancestor.
+pub fn device_of(p: &path::Path) -> io::Result<u64> {
+    let mut current = p;
+    loop {
+        match fs::metadata(current) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match current.parent() {
+                Some(parent) => current = parent,
+                None => return Err(err),
+            },
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn read_dev(sys_dir: &path::Path) -> Option<u64> {
+    let dev = fs::read_to_string(sys_dir.join("dev")).ok()?;
+    let (major, minor) = dev.trim().split_once(':')?;
+    Some(libc::makedev(major.parse().ok()?, minor.parse().ok()?))
+}
+
+// The disk a filesystem device lives on: partitions map to their disk, and
+// device-mapper/md devices backed by a single device (e.g. dm-crypt) map to
+// whatever backs them. Anything else (tmpfs, network filesystems, RAID) is its
+// own device.
+pub fn physical_device(dev: u64) -> u64 {
+    let sys = path::PathBuf::from(format!(
+        "/sys/dev/block/{}:{}",
+        libc::major(dev),
+        libc::minor(dev)
+    ));
+    let Ok(mut sys_dir) = fs::canonicalize(&sys) else {
+        return dev;
+    };
+
+    loop {
+        if sys_dir.join("partition").exists() {
+            match sys_dir.parent() {
+                Some(disk) => sys_dir = disk.to_path_buf(),
+                None => break,
+            }
+            continue;
+        }
+        let slaves: Vec<path::PathBuf> = match fs::read_dir(sys_dir.join("slaves")) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => break,
+        };
+        match slaves.as_slice() {
+            [slave] => match fs::canonicalize(slave) {
+                Ok(slave) => sys_dir = slave,
+                Err(_) => break,
+            },
+            _ => break,
+        }
+    }
+
+    read_dev(&sys_dir).unwrap_or(dev)
+}
+
+// Physical devices `paths` live on, sorted and without duplicates. Paths that
+// can't be looked up are left out; the job will report them when it runs.
+pub fn devices_for(paths: &[&path::Path]) -> Vec<u64> {
+    let mut devices: Vec<u64> = paths
+        .iter()
+        .filter_map(|p| device_of(p).ok())
+        .map(physical_device)
+        .collect();
+    devices.sort();
+    devices.dedup();
+    devices
+}
+
+#[derive(Default)]
+struct State {
+    next_ticket: u64,
+    // In submission order.
+    waiting: Vec<(u64, Vec<u64>)>,
+    busy: collections::HashSet<u64>,
+}
+
+// Lets at most one job at a time touch each physical device. Jobs on the same
+// device run in the order they were enqueued; jobs on different devices run in
+// parallel.
+#[derive(Default)]
+pub struct Scheduler {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+// Held while a job runs; frees its devices when dropped.
+pub struct Claim<'s> {
+    scheduler: &'s Scheduler,
+    devices: Vec<u64>,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        for device in &self.devices {
+            state.busy.remove(device);
+        }
+        self.scheduler.released.notify_all();
+    }
+}
+
+impl Scheduler {
+    pub fn enqueue(&self, devices: Vec<u64>) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push((ticket, devices));
+        ticket
+    }
+
+    // Blocks until every device of `ticket` is free and no job enqueued before
+    // it is still waiting for one of them.
+    pub fn wait(&self, ticket: u64) -> Claim<'_> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(devices) = claim(&mut state, ticket) {
+                return Claim {
+                    scheduler: self,
+                    devices,
+                };
+            }
+            state = self.released.wait(state).unwrap();
+        }
+    }
+
+    #[cfg(test)]
+    fn try_claim(&self, ticket: u64) -> Option<Claim<'_>> {
+        let devices = claim(&mut self.state.lock().unwrap(), ticket)?;
+        Some(Claim {
+            scheduler: self,
+            devices,
+        })
+    }
+}
+
+fn claim(state: &mut State, ticket: u64) -> Option<Vec<u64>> {
+    let idx = state.waiting.iter().position(|(t, _)| *t == ticket)?;
+    let devices = &state.waiting[idx].1;
+    let blocked = devices.iter().any(|d| state.busy.contains(d))
+        || state.waiting[..idx]
+            .iter()
+            .any(|(_, earlier)| earlier.iter().any(|d| devices.contains(d)));
+    if blocked {
+        return None;
+    }
+
+    let (_, devices) = state.waiting.remove(idx);
+    state.busy.extend(devices.iter().copied());
+    Some(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_on_a_device_run_one_at_a_time_in_order() {
+        let scheduler = Scheduler::default();
+        let a = scheduler.enqueue(vec![1]);
+        let ab = scheduler.enqueue(vec![1, 2]);
+        let b = scheduler.enqueue(vec![2]);
+        let c = scheduler.enqueue(vec![3]);
+
+        let claim_a = scheduler.try_claim(a).unwrap();
+        assert!(scheduler.try_claim(ab).is_none());
+        // Device 2 is free, but `ab` was there first.
+        assert!(scheduler.try_claim(b).is_none());
+        let claim_c = scheduler.try_claim(c).unwrap();
+
+        drop(claim_a);
+        let claim_ab = scheduler.try_claim(ab).unwrap();
+        assert!(scheduler.try_claim(b).is_none());
+        drop(claim_ab);
+        assert!(scheduler.try_claim(b).is_some());
+        drop(claim_c);
+    }
+}
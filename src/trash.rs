@@ -0,0 +1,337 @@
+use std::env;
+use std::ffi;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path;
+use std::time;
+
+use crate::scheduler;
+
+// Implements the freedesktop.org Trash specification
+// (https://specifications.freedesktop.org/trash-spec/latest/).
+
+const INFO_EXTENSION: &str = "trashinfo";
+
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+fn home_trash() -> Option<path::PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+        _ => path::PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_home.join("Trash"))
+}
+
+fn mount_point(p: &path::Path, dev: u64) -> path::PathBuf {
+    let mut top = p;
+    while let Some(parent) = top.parent() {
+        match fs::metadata(parent) {
+            Ok(metadata) if metadata.dev() == dev => top = parent,
+            _ => break,
+        }
+    }
+    top.to_path_buf()
+}
+
+fn create_private_dir(dir: &path::Path) -> io::Result<()> {
+    match fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+    {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists && dir.is_dir() => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+// The trash directory for a file on device `dev` and the directory that
+// relative `Path=` keys in it are resolved against, if any.
+fn trash_dir_for(
+    target: &path::Path,
+    dev: u64,
+) -> io::Result<(path::PathBuf, Option<path::PathBuf>)> {
+    if let Some(home_trash) = home_trash()
+        && scheduler::device_of(&home_trash)? == dev
+    {
+        return Ok((home_trash, None));
+    }
+
+    let top = mount_point(target, dev);
+    let uid = uid();
+
+    // $topdir/.Trash/$uid, only if the admin set up a sticky, non-symlink .Trash.
+    let shared = top.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared)
+        && metadata.is_dir()
+        && metadata.mode() & libc::S_ISVTX != 0
+    {
+        let trash = shared.join(uid.to_string());
+        if create_private_dir(&trash).is_ok() {
+            return Ok((trash, Some(top)));
+        }
+    }
+
+    // $topdir/.Trash-$uid
+    let trash = top.join(format!(".Trash-{}", uid));
+    create_private_dir(&trash)?;
+    let metadata = fs::symlink_metadata(&trash)?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a usable trash directory", trash.display()),
+        ));
+    }
+    Ok((trash, Some(top)))
+}
+
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for b in bytes {
+        if b.is_ascii_alphanumeric() || b"-_.~/".contains(b) {
+            encoded.push(*b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Some(b) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(b);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    decoded
+}
+
+fn deletion_date() -> String {
+    let now = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+// Claims a name in the trash by exclusively creating its info file, which is
+// what makes concurrent trashing from several programs safe.
+fn reserve_name(trash: &path::Path, name: &ffi::OsStr) -> io::Result<(ffi::OsString, fs::File)> {
+    for n in 1.. {
+        let mut candidate = name.to_os_string();
+        if n > 1 {
+            candidate.push(format!(".{}", n));
+        }
+        if fs::symlink_metadata(trash.join("files").join(&candidate)).is_ok() {
+            continue;
+        }
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(info_path(trash, &candidate))
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+fn info_path(trash: &path::Path, name: &ffi::OsStr) -> path::PathBuf {
+    let mut info_name = name.to_os_string();
+    info_name.push(".");
+    info_name.push(INFO_EXTENSION);
+    trash.join("info").join(info_name)
+}
+
+// Moves `target` into the trash of the filesystem it lives on and returns its
+// new location.
+pub fn trash(target: &path::Path) -> io::Result<path::PathBuf> {
+    let target = path::absolute(target)?;
+    let dev = fs::symlink_metadata(&target)?.dev();
+    let (trash, top) = trash_dir_for(&target, dev)?;
+    trash_into(&target, &trash, top.as_deref())
+}
+
+fn trash_into(
+    target: &path::Path,
+    trash: &path::Path,
+    top: Option<&path::Path>,
+) -> io::Result<path::PathBuf> {
+    let name = target.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot trash {}", target.display()),
+        )
+    })?;
+    create_private_dir(&trash.join("files"))?;
+    create_private_dir(&trash.join("info"))?;
+
+    let original = match top {
+        Some(top) => target.strip_prefix(top).unwrap_or(target),
+        None => target,
+    };
+    let (trashed_name, mut info) = reserve_name(trash, name)?;
+    let trashed = trash.join("files").join(&trashed_name);
+
+    let written = write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original.as_os_str().as_bytes()),
+        deletion_date()
+    )
+    .and_then(|()| info.sync_all())
+    .and_then(|()| fs::rename(target, &trashed));
+
+    match written {
+        Ok(()) => Ok(trashed),
+        Err(err) => {
+            let _ = fs::remove_file(info_path(trash, &trashed_name));
+            Err(err)
+        }
+    }
+}
+
+// Whether `dir` is the `files` directory of a trash, where restore takes
+// entries from.
+pub fn is_files_dir(dir: &path::Path) -> bool {
+    dir.file_name() == Some(ffi::OsStr::new("files"))
+        && dir
+            .parent()
+            .is_some_and(|trash| trash.join("info").is_dir())
+}
+
+// Puts an entry from a trash `files` directory back where it was trashed from
+// and returns that location.
+pub fn restore(trashed: &path::Path) -> io::Result<path::PathBuf> {
+    let not_in_trash = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not in a trash directory", trashed.display()),
+        )
+    };
+    let name = trashed.file_name().ok_or_else(not_in_trash)?;
+    let files = trashed.parent().ok_or_else(not_in_trash)?;
+    if files.file_name() != Some(ffi::OsStr::new("files")) {
+        return Err(not_in_trash());
+    }
+    let trash = files.parent().ok_or_else(not_in_trash)?;
+    let info_path = info_path(trash, name);
+
+    let info = fs::read_to_string(&info_path)?;
+    let encoded = info
+        .lines()
+        .find_map(|line| line.strip_prefix("Path="))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no Path", info_path.display()),
+            )
+        })?;
+    let original = path::PathBuf::from(ffi::OsString::from_vec(percent_decode(encoded)));
+
+    // Relative paths are relative to the top of the mount the trash lives on:
+    // the parent of .Trash-$uid, or of .Trash for .Trash/$uid.
+    let original = if original.is_absolute() {
+        original
+    } else {
+        let top = if trash
+            .file_name()
+            .is_some_and(|n| n.as_bytes().starts_with(b".Trash-"))
+        {
+            trash.parent()
+        } else {
+            trash.parent().and_then(|p| p.parent())
+        };
+        top.ok_or_else(not_in_trash)?.join(original)
+    };
+
+    if fs::symlink_metadata(&original).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", original.display()),
+        ));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(trashed, &original)?;
+    fs::remove_file(&info_path)?;
+    Ok(original)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trash_and_restore_round_trip() {
+        let root = env::temp_dir().join(format!("files_please-trash-{}", uuidv7::create()));
+        let dir = root.join("some dir");
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("100% done.txt");
+        fs::write(&target, b"bye").unwrap();
+        let trash = root.join(format!(".Trash-{}", uid()));
+        create_private_dir(&trash).unwrap();
+
+        let trashed = trash_into(&target, &trash, Some(&root)).unwrap();
+        assert!(!target.exists());
+        assert_eq!(fs::read(&trashed).unwrap(), b"bye");
+        assert!(is_files_dir(trashed.parent().unwrap()));
+        assert!(!is_files_dir(&dir));
+
+        // Same name again gets its own slot.
+        fs::write(&target, b"again").unwrap();
+        let trashed_again = trash_into(&target, &trash, Some(&root)).unwrap();
+        assert!(
+            fs::read_to_string(info_path(&trash, trashed_again.file_name().unwrap()))
+                .unwrap()
+                .contains("Path=some%20dir/100%25%20done.txt\n")
+        );
+        assert_ne!(trashed, trashed_again);
+
+        let restored = restore(&trashed_again).unwrap();
+        assert_eq!(restored, target);
+        assert_eq!(fs::read(&target).unwrap(), b"again");
+        assert!(restore(&trashed).is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        let raw = b"/tmp/a b/%x/\xff";
+        let encoded = percent_encode(raw);
+        assert_eq!(encoded, "/tmp/a%20b/%25x/%FF");
+        assert_eq!(percent_decode(&encoded), raw);
+    }
+}
@@ -2,6 +2,7 @@ use std::collections;
 use std::error;
 use std::mem;
 use std::path;
+use std::time;
 
 extern crate sdl3;
 use sdl3::pixels;
@@ -11,7 +12,11 @@ use sdl3::ttf;
 use sdl3::video;
 
 use crate::directory;
+use crate::finder;
+use crate::iobackend;
 use crate::jobs;
+use crate::progress;
+use crate::watcher;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
@@ -60,6 +65,24 @@ impl<'t> TextureManager<'t> {
     fn get(&self, tid: Entity) -> Option<&render::Texture<'t>> {
         self.cache.get(&tid)
     }
+
+    // For text that changes from frame to frame, like counters: the texture is
+    // dropped after drawing instead of being cached with every value it had.
+    fn render_uncached(
+        &self,
+        canvas: &mut render::Canvas<video::Window>,
+        font: &ttf::Font,
+        text: &str,
+        colour: pixels::Color,
+        x: f32,
+        y: f32,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let surface = font.render(text).blended(colour)?;
+        let tex = self.creator.create_texture_from_surface(surface)?;
+        let target = render::FRect::new(x, y, tex.width() as f32, tex.height() as f32);
+        let _ = canvas.copy(&tex, None, Some(target));
+        Ok(())
+    }
 }
 
 struct TextManager {
@@ -123,6 +146,18 @@ impl TextManager {
     }
 }
 
+// What the views draw with.
+struct RenderContext<'r, 't> {
+    canvas: &'r mut render::Canvas<video::Window>,
+    theme: &'r Theme,
+    entity_manager: &'r mut EntityManager,
+    text_manager: &'r mut TextManager,
+    texture_manager: &'r mut TextureManager<'t>,
+    font: &'r ttf::Font<'r, 'r>,
+    // Shown next to the names in the directory views.
+    columns: &'r [Column],
+}
+
 pub struct Theme {
     active: pixels::Color,
     inactive: pixels::Color,
@@ -133,6 +168,8 @@ pub struct Theme {
     header: pixels::Color,
     selected: pixels::Color,
     scrollbar: pixels::Color,
+    error: pixels::Color,
+    symlink: pixels::Color,
     byte: pixels::Color,
     kilo: pixels::Color,
     mega: pixels::Color,
@@ -151,12 +188,167 @@ impl Theme {
             header: pixels::Color::RGB(250, 250, 250),
             selected: pixels::Color::RGB(250, 120, 0),
             scrollbar: pixels::Color::RGB(180, 180, 180),
+            error: pixels::Color::RGB(230, 60, 60),
+            symlink: pixels::Color::RGB(60, 210, 230),
             byte: pixels::Color::RGB(100, 160, 20),
             kilo: pixels::Color::RGB(140, 160, 20),
             mega: pixels::Color::RGB(180, 160, 20),
             giga: pixels::Color::RGB(240, 160, 20),
         }
     }
+
+    fn magnitude(&self, bytes: u64) -> pixels::Color {
+        match progress::magnitude(bytes) {
+            progress::Magnitude::Byte => self.byte,
+            progress::Magnitude::Kilo => self.kilo,
+            progress::Magnitude::Mega => self.mega,
+            progress::Magnitude::Giga => self.giga,
+        }
+    }
+}
+
+// Entry metadata shown next to the names in directory views.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Column {
+    Size,
+    Modified,
+    Changed,
+    Born,
+    Mode,
+    Owner,
+    Group,
+    Links,
+    Inode,
+}
+
+// The sets of columns F9 switches between, the first one by default.
+const COLUMN_SETS: [&[Column]; 4] = [
+    &[Column::Size, Column::Modified],
+    &[
+        Column::Mode,
+        Column::Links,
+        Column::Owner,
+        Column::Group,
+        Column::Size,
+        Column::Modified,
+    ],
+    &[
+        Column::Size,
+        Column::Modified,
+        Column::Changed,
+        Column::Born,
+    ],
+    &[Column::Inode, Column::Links],
+];
+
+impl Column {
+    fn title(self) -> &'static str {
+        match self {
+            Column::Size => "size",
+            Column::Modified => "modified",
+            Column::Changed => "changed",
+            Column::Born => "born",
+            Column::Mode => "mode",
+            Column::Owner => "owner",
+            Column::Group => "group",
+            Column::Links => "links",
+            Column::Inode => "inode",
+        }
+    }
+
+    fn width(self) -> f32 {
+        match self {
+            Column::Size => 100.0,
+            Column::Modified | Column::Changed | Column::Born => 160.0,
+            Column::Mode => 110.0,
+            Column::Owner | Column::Group => 90.0,
+            Column::Links => 60.0,
+            Column::Inode => 110.0,
+        }
+    }
+
+    fn text(self, entry: &directory::Entry) -> String {
+        let stat = &entry.stat;
+        match self {
+            // What a directory's size says depends on the filesystem.
+            Column::Size if entry.kind == directory::EntryKind::Dir => String::new(),
+            Column::Size => progress::format_bytes(stat.size),
+            Column::Modified => format_time(stat.mtime),
+            Column::Changed => format_time(stat.ctime),
+            Column::Born => stat.btime.map(format_time).unwrap_or_default(),
+            Column::Mode => format_mode(stat.mode),
+            Column::Owner => entry.owner.clone(),
+            Column::Group => entry.group.clone(),
+            Column::Links => stat.nlink.to_string(),
+            Column::Inode => stat.ino.to_string(),
+        }
+    }
+}
+
+// Local time, to the minute.
+fn format_time(secs: i64) -> String {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return String::new();
+    }
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min
+    )
+}
+
+// Like `ls -l`, e.g. "drwxr-xr-x".
+fn format_mode(mode: u32) -> String {
+    let kind = match mode & libc::S_IFMT {
+        libc::S_IFDIR => 'd',
+        libc::S_IFLNK => 'l',
+        libc::S_IFIFO => 'p',
+        libc::S_IFSOCK => 's',
+        libc::S_IFBLK => 'b',
+        libc::S_IFCHR => 'c',
+        _ => '-',
+    };
+    let mut text = String::from(kind);
+    for (shift, special, special_char) in [
+        (6, libc::S_ISUID, 's'),
+        (3, libc::S_ISGID, 's'),
+        (0, libc::S_ISVTX, 't'),
+    ] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+fn sort_text(sort: directory::SortOrder) -> String {
+    let mut text = String::from(match sort.key {
+        directory::SortKey::Name => "name",
+        directory::SortKey::Size => "size",
+        directory::SortKey::Modified => "modified",
+        directory::SortKey::Extension => "extension",
+        directory::SortKey::Kind => "kind",
+    });
+    if sort.reverse {
+        text.push_str(" reversed");
+    }
+    if sort.case_sensitive {
+        text.push_str(", case-sensitive");
+    }
+    if sort.dirs_first {
+        text.push_str(", dirs first");
+    }
+    text
 }
 
 pub struct DirectoryViewEntry {
@@ -171,18 +363,41 @@ pub struct DirectoryView {
     line_height: f32,
     scroll_index: usize,
     selected_index: Option<usize>,
+    sort: directory::SortOrder,
+    search: Option<Search>,
+    // Set while the entries are still coming in.
+    loading: Option<directory::Loading>,
+    // How many entries there were when they were last sorted.
+    sorted: usize,
+    // The entry to put the cursor on once it has been loaded.
+    wanted: Option<path::PathBuf>,
+    refresh: Option<Refresh>,
 }
 
-impl From<&directory::Entries> for DirectoryView {
-    fn from(de: &directory::Entries) -> DirectoryView {
-        let mut dv = DirectoryView::new(de.absolute_path.clone());
-        for entry in de.entries.iter() {
-            dv.entries.push(DirectoryViewEntry {
-                entry: entry.clone(),
-                selected: false,
-            });
-        }
+// A fresh listing of a view's directory, merged in once complete so the view
+// doesn't empty out meanwhile.
+struct Refresh {
+    loading: directory::Loading,
+    entries: Vec<directory::Entry>,
+    // Names the watcher reported while the listing was being read, which it
+    // may have missed.
+    changed: collections::HashSet<path::PathBuf>,
+}
+
+// Jumps the cursor to entries whose names contain the query.
+struct Search {
+    query: String,
+    // Where the cursor was when the search started, to go back to on cancel.
+    origin: Option<usize>,
+    // Indices of the matching entries, in order. Kept up to date as the query
+    // and the entries change rather than worked out every frame.
+    matches: Vec<usize>,
+}
 
+impl From<directory::Loading> for DirectoryView {
+    fn from(loading: directory::Loading) -> DirectoryView {
+        let mut dv = DirectoryView::new(loading.absolute_path.clone());
+        dv.loading = Some(loading);
         dv
     }
 }
@@ -196,6 +411,317 @@ impl DirectoryView {
             line_height: 24.0,
             scroll_index: 0,
             selected_index: None,
+            sort: directory::SortOrder::default(),
+            search: None,
+            loading: None,
+            sorted: 0,
+            wanted: None,
+            refresh: None,
+        }
+    }
+
+    // Reads the directory again. Until the listing is complete the view keeps
+    // showing what it has.
+    pub fn refresh(&mut self) {
+        if self.loading.is_some() {
+            // Still being read for the first time.
+            return;
+        }
+        let loading = match directory::Loading::open(self.dir.clone()) {
+            Ok(loading) => loading,
+            Err(err) => {
+                eprintln!("Refresh {} err={}", self.dir.display(), err);
+                return;
+            }
+        };
+        self.refresh = Some(Refresh {
+            loading,
+            entries: vec![],
+            changed: collections::HashSet::new(),
+        });
+    }
+
+    fn poll_refresh(&mut self) {
+        let Some(refresh) = &mut self.refresh else {
+            return;
+        };
+        let (batch, done) = refresh.loading.poll();
+        refresh.entries.extend(batch);
+        if done && let Some(refresh) = self.refresh.take() {
+            self.merge(refresh.entries);
+            if !refresh.changed.is_empty() {
+                self.apply_changes(&refresh.changed);
+            }
+        }
+    }
+
+    // Replaces the entries with `fresh` ones. Entries keep their selection
+    // and the cursor stays on its entry when one with the same inode or name
+    // is still there.
+    fn merge(&mut self, fresh: Vec<directory::Entry>) {
+        let hovered = self.hovered_entry();
+        let mut selected_inodes = collections::HashSet::new();
+        let mut selected_names = collections::HashSet::new();
+        for e in self.entries.iter().filter(|e| e.selected) {
+            selected_inodes.insert(e.entry.stat.ino);
+            selected_names.insert(e.entry.name.clone());
+        }
+        self.entries = fresh
+            .into_iter()
+            .map(|entry| DirectoryViewEntry {
+                selected: selected_inodes.contains(&entry.stat.ino)
+                    || selected_names.contains(&entry.name),
+                entry,
+            })
+            .collect();
+        let sort = self.sort;
+        self.entries
+            .sort_by(|a, b| sort.compare(&a.entry, &b.entry));
+        self.sorted = self.entries.len();
+
+        let same = hovered.and_then(|hovered| {
+            self.entries
+                .iter()
+                .position(|e| e.entry.stat.ino == hovered.stat.ino)
+                .or_else(|| {
+                    self.entries
+                        .iter()
+                        .position(|e| e.entry.name == hovered.name)
+                })
+        });
+        match (same, self.selected_index) {
+            (Some(index), _) => self.hover(index),
+            (None, Some(index)) if !self.entries.is_empty() => {
+                self.hover(index.min(self.entries.len() - 1))
+            }
+            _ => self.selected_index = None,
+        }
+        self.update_matches();
+    }
+
+    // Takes in the entries read since the last call.
+    pub fn poll_loading(&mut self) {
+        self.poll_refresh();
+        let Some(loading) = &self.loading else {
+            return;
+        };
+        let (batch, done) = loading.poll();
+        let added = !batch.is_empty();
+        self.entries
+            .extend(batch.into_iter().map(|entry| DirectoryViewEntry {
+                entry,
+                selected: false,
+            }));
+        if done {
+            self.loading = None;
+        }
+        // Re-sorting half a million entries every frame would freeze the
+        // window all the same, so while loading they are sorted whenever their
+        // number doubles, and once more at the end.
+        if (added && self.entries.len() >= self.sorted * 2)
+            || (done && self.entries.len() != self.sorted)
+        {
+            self.set_sort(self.sort);
+            self.sorted = self.entries.len();
+        } else if added {
+            self.update_matches();
+        }
+        if let Some(name) = self.wanted.take() {
+            self.hover_name(&name);
+        }
+        if done {
+            self.wanted = None;
+        }
+        if self.selected_index.is_none() && !self.entries.is_empty() {
+            self.selected_index = Some(0);
+        }
+    }
+
+    // Brings the entries called `names` in line with what is there now. The
+    // cursor and the selection follow entries by inode, so they survive
+    // renames.
+    pub fn apply_changes(&mut self, names: &collections::HashSet<path::PathBuf>) {
+        if let Some(refresh) = &mut self.refresh {
+            refresh.changed.extend(names.iter().cloned());
+        }
+        let hovered = self.hovered_entry().map(|entry| entry.stat.ino);
+        let selected: collections::HashSet<u64> = self
+            .entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| e.entry.stat.ino)
+            .collect();
+        self.entries.retain(|e| !names.contains(&e.entry.name));
+        for name in names {
+            let Some(entry) = directory::lookup(&self.dir.join(name)) else {
+                continue;
+            };
+            let dve = DirectoryViewEntry {
+                selected: selected.contains(&entry.stat.ino),
+                entry,
+            };
+            if self.loading.is_some() {
+                // Sorted with the rest once loaded.
+                self.entries.push(dve);
+            } else {
+                let sort = self.sort;
+                let index = self
+                    .entries
+                    .partition_point(|e| sort.compare(&e.entry, &dve.entry).is_lt());
+                self.entries.insert(index, dve);
+            }
+        }
+        if self.loading.is_none() {
+            self.sorted = self.entries.len();
+        }
+
+        let by_inode =
+            hovered.and_then(|ino| self.entries.iter().position(|e| e.entry.stat.ino == ino));
+        match (by_inode, self.selected_index) {
+            (Some(index), _) => self.hover(index),
+            // Gone: stay at the same height.
+            (None, Some(index)) if !self.entries.is_empty() => {
+                self.hover(index.min(self.entries.len() - 1))
+            }
+            _ => self.selected_index = None,
+        }
+        self.update_matches();
+    }
+
+    // Puts the cursor on the entry called `name`, or on it once it is loaded.
+    pub fn hover_name(&mut self, name: &path::Path) {
+        match self.entries.iter().position(|e| e.entry.name == name) {
+            Some(index) => self.hover(index),
+            None if self.loading.is_some() && !name.as_os_str().is_empty() => {
+                self.wanted = Some(name.to_path_buf())
+            }
+            None => {}
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.search = Some(Search {
+            query: String::new(),
+            origin: self.selected_index,
+            matches: vec![],
+        });
+    }
+
+    pub fn edit_search(&mut self, edit: impl FnOnce(&mut String)) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        edit(&mut search.query);
+        let origin = search.origin;
+        self.update_matches();
+        // Typing narrows down from where the search started, not from the
+        // previous match.
+        match self.find_match(origin.unwrap_or(0), true) {
+            Some(index) => self.hover(index),
+            None => self.selected_index = origin,
+        }
+    }
+
+    // Keeps the query around for next_match.
+    pub fn commit_search(&mut self) {
+        if self.search.as_ref().is_some_and(|s| s.query.is_empty()) {
+            self.search = None;
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.selected_index = search.origin;
+            if let Some(index) = search.origin {
+                self.scroll_to(index);
+            }
+        }
+    }
+
+    pub fn next_match(&mut self, forward: bool) {
+        let Some(current) = self.selected_index else {
+            return;
+        };
+        let len = self.entries.len();
+        if len == 0 {
+            return;
+        }
+        let start = if forward {
+            (current + 1) % len
+        } else {
+            (current + len - 1) % len
+        };
+        if let Some(index) = self.find_match(start, forward) {
+            self.hover(index);
+        }
+    }
+
+    // The first match from `start` on, wrapping around.
+    fn find_match(&self, start: usize, forward: bool) -> Option<usize> {
+        let matches = &self.search.as_ref()?.matches;
+        if forward {
+            let after = matches.partition_point(|&index| index < start);
+            matches.get(after).or(matches.first()).copied()
+        } else {
+            let after = matches.partition_point(|&index| index <= start);
+            after
+                .checked_sub(1)
+                .map(|before| matches[before])
+                .or(matches.last().copied())
+        }
+    }
+
+    // Finds the matches again after the query or the entries changed.
+    fn update_matches(&mut self) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        search.matches.clear();
+        if search.query.is_empty() {
+            return;
+        }
+        // Case-insensitive unless the query has capitals in it.
+        let case_sensitive = search.query.chars().any(char::is_uppercase);
+        let query = if case_sensitive {
+            search.query.clone()
+        } else {
+            search.query.to_lowercase()
+        };
+        search.matches.extend(
+            self.entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| name_matches(&e.entry.name, &query, case_sensitive))
+                .map(|(index, _)| index),
+        );
+    }
+
+    fn hover(&mut self, index: usize) {
+        self.selected_index = Some(index);
+        self.scroll_to(index);
+    }
+
+    // Re-sorts the entries, keeping the cursor on the same entry.
+    pub fn set_sort(&mut self, sort: directory::SortOrder) {
+        let hovered = self.hovered_entry().map(|entry| entry.name);
+        self.sort = sort;
+        self.entries
+            .sort_by(|a, b| sort.compare(&a.entry, &b.entry));
+        self.update_matches();
+        if let Some(name) = hovered {
+            self.selected_index = self.entries.iter().position(|e| e.entry.name == name);
+        }
+        if let Some(index) = self.selected_index {
+            self.scroll_to(index);
+        }
+    }
+
+    fn scroll_to(&mut self, index: usize) {
+        let num_lines = DirectoryView::num_lines(self.draw_region.h, self.line_height);
+        if index < self.scroll_index {
+            self.scroll_index = index;
+        } else if num_lines > 0 && index >= self.scroll_index + num_lines {
+            self.scroll_index = index - num_lines + 1;
         }
     }
 
@@ -208,6 +734,7 @@ impl DirectoryView {
     }
 
     pub fn top(&mut self) {
+        self.wanted = None;
         if self.entries.len() > 0 {
             self.selected_index = Some(0);
             self.scroll_index = 0;
@@ -222,6 +749,7 @@ impl DirectoryView {
     }
 
     pub fn up(&mut self, distance: usize) {
+        self.wanted = None;
         if let Some(current) = self.selected_index {
             let delta = if current < distance {
                 current
@@ -238,6 +766,7 @@ impl DirectoryView {
     }
 
     pub fn down(&mut self, distance: usize) {
+        self.wanted = None;
         if let Some(current) = self.selected_index {
             let delta = if current + distance < self.entries.len() {
                 distance
@@ -268,16 +797,34 @@ impl DirectoryView {
         None
     }
 
-    fn render(
-        &self,
-        canvas: &mut render::Canvas<video::Window>,
-        theme: &Theme,
-        entity_manager: &mut EntityManager,
-        text_manager: &mut TextManager,
-        texture_manager: &mut TextureManager,
-        active: bool,
-        font: &sdl3::ttf::Font,
-    ) -> Result<(), Box<dyn error::Error>> {
+    // Absolute paths of the selected entries, or of the hovered entry when
+    // nothing is selected.
+    pub fn selected_paths(&self) -> Vec<path::PathBuf> {
+        let selected: Vec<path::PathBuf> = self
+            .entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| self.dir.join(&e.entry.name))
+            .collect();
+        if !selected.is_empty() {
+            return selected;
+        }
+        match self.hovered_entry() {
+            Some(entry) => vec![self.dir.join(entry.name)],
+            None => vec![],
+        }
+    }
+
+    fn render(&self, cx: &mut RenderContext, active: bool) -> Result<(), Box<dyn error::Error>> {
+        let RenderContext {
+            canvas,
+            theme,
+            entity_manager,
+            text_manager,
+            texture_manager,
+            font,
+            columns,
+        } = cx;
         canvas.set_draw_color(if active { theme.active } else { theme.inactive });
         let _ = canvas.fill_rect(self.draw_region);
 
@@ -292,18 +839,56 @@ impl DirectoryView {
         let padding = 5.0;
         let mut next = 0.0;
 
-        if let Some(text) = self.dir.clone().into_os_string().to_str() {
+        // Right-aligned as a block, clear of the scrollbar.
+        let columns_x = self.draw_region.x + self.draw_region.w
+            - columns.iter().map(|c| c.width()).sum::<f32>()
+            - padding * 2.0;
+        let mut column_x = columns_x;
+        for column in columns.iter() {
             let _ = text_manager.render(
                 entity_manager,
                 texture_manager,
                 canvas,
                 font,
-                text,
+                column.title(),
                 theme.header,
                 18,
-                self.draw_region.x + padding,
+                column_x,
                 self.draw_region.y + padding + next,
             );
+            column_x += column.width();
+        }
+
+        if let Some(text) = self.dir.clone().into_os_string().to_str() {
+            let x = self.draw_region.x + padding;
+            let y = self.draw_region.y + padding + next;
+            // The count goes up while loading, so it isn't cached.
+            let _ = match self.loading {
+                Some(_) => texture_manager.render_uncached(
+                    canvas,
+                    font,
+                    &format!(
+                        "{}  [{}]  loading {} entries…",
+                        text,
+                        sort_text(self.sort),
+                        self.entries.len()
+                    ),
+                    theme.header,
+                    x,
+                    y,
+                ),
+                None => text_manager.render(
+                    entity_manager,
+                    texture_manager,
+                    canvas,
+                    font,
+                    &format!("{}  [{}]", text, sort_text(self.sort)),
+                    theme.header,
+                    18,
+                    x,
+                    y,
+                ),
+            };
             next += 28.0;
         };
         //let surface = font.render(text).blended(theme.header)?;
@@ -313,6 +898,11 @@ impl DirectoryView {
         let icon_width = 20.0;
         let dir_icon = "\u{f4d3}";
         let file_icon = " ";
+        let symlink_icon = "\u{f481}";
+        let fifo_icon = "\u{f07e}";
+        let socket_icon = "\u{f1e6}";
+        let block_device_icon = "\u{f0a0}";
+        let char_device_icon = "\u{f120}";
         let select_width = 4.0;
         let file_size_width = 0.0;
 
@@ -359,10 +949,14 @@ impl DirectoryView {
                     texture_manager,
                     canvas,
                     font,
-                    if entry.entry.kind == directory::EntryKind::Dir {
-                        dir_icon
-                    } else {
-                        file_icon
+                    match entry.entry.kind {
+                        directory::EntryKind::Dir => dir_icon,
+                        directory::EntryKind::File => file_icon,
+                        directory::EntryKind::Symlink { .. } => symlink_icon,
+                        directory::EntryKind::Fifo => fifo_icon,
+                        directory::EntryKind::Socket => socket_icon,
+                        directory::EntryKind::BlockDevice => block_device_icon,
+                        directory::EntryKind::CharDevice => char_device_icon,
                     },
                     theme.text,
                     18,
@@ -370,13 +964,20 @@ impl DirectoryView {
                     self.draw_region.y + padding + next,
                 );
 
+                let (text, color) = match &entry.entry.kind {
+                    directory::EntryKind::Symlink { target, broken } => (
+                        format!("{} -> {}", text, target.display()),
+                        if *broken { theme.error } else { theme.symlink },
+                    ),
+                    _ => (String::from(text), theme.text),
+                };
                 let _ = text_manager.render(
                     entity_manager,
                     texture_manager,
                     canvas,
                     font,
-                    text,
-                    theme.text,
+                    &text,
+                    color,
                     18,
                     self.draw_region.x
                         + file_size_width
@@ -386,23 +987,27 @@ impl DirectoryView {
                     self.draw_region.y + padding + next,
                 );
 
-                let inode_text = format!("{}", entry.entry.inode);
-                let _ = text_manager.render(
-                    entity_manager,
-                    texture_manager,
-                    canvas,
-                    font,
-                    &inode_text,
-                    theme.text,
-                    18,
-                    self.draw_region.x
-                        + file_size_width
-                        + select_width * 2.0
-                        + icon_width
-                        + padding
-                        + 500.0,
-                    self.draw_region.y + padding + next,
-                );
+                let mut column_x = columns_x;
+                for column in columns.iter() {
+                    let column_text = column.text(&entry.entry);
+                    if !column_text.is_empty() {
+                        let _ = text_manager.render(
+                            entity_manager,
+                            texture_manager,
+                            canvas,
+                            font,
+                            &column_text,
+                            match column {
+                                Column::Size => theme.magnitude(entry.entry.stat.size),
+                                _ => theme.text,
+                            },
+                            18,
+                            column_x,
+                            self.draw_region.y + padding + next,
+                        );
+                    }
+                    column_x += column.width();
+                }
 
                 next += 24.0;
             }
@@ -425,52 +1030,407 @@ impl DirectoryView {
             }
         }
 
+        if let Some(search) = &self.search {
+            let y = self.draw_region.y + self.draw_region.h - self.line_height - padding;
+            canvas.set_draw_color(if active { theme.active } else { theme.inactive });
+            let _ = canvas.fill_rect(render::FRect::new(
+                self.draw_region.x,
+                y,
+                self.draw_region.w,
+                self.line_height + padding,
+            ));
+            let matches = search.matches.len();
+            let text = match (search.query.is_empty(), matches) {
+                (true, _) => String::from("/"),
+                (false, 0) => format!("/{}  (no match)", search.query),
+                (false, n) => format!(
+                    "/{}  ({} match{})",
+                    search.query,
+                    n,
+                    if n == 1 { "" } else { "es" }
+                ),
+            };
+            let _ = texture_manager.render_uncached(
+                canvas,
+                font,
+                &text,
+                if search.query.is_empty() || matches > 0 {
+                    theme.header
+                } else {
+                    theme.error
+                },
+                self.draw_region.x + padding,
+                y,
+            );
+        }
+
         Ok(())
     }
 }
 
+// A case-insensitive `query` is expected in lowercase.
+fn name_matches(name: &path::Path, query: &str, case_sensitive: bool) -> bool {
+    let name = name.to_string_lossy();
+    if case_sensitive {
+        name.contains(query)
+    } else {
+        name.to_lowercase().contains(query)
+    }
+}
+
+// The fuzzy finder, drawn over both directory views.
+struct FinderView {
+    finder: finder::Finder,
+    selected: usize,
+    draw_region: render::FRect,
+    line_height: f32,
+}
+
+impl FinderView {
+    fn new(finder: finder::Finder) -> FinderView {
+        FinderView {
+            finder,
+            selected: 0,
+            draw_region: render::FRect::new(0.0, 0.0, 0.0, 0.0),
+            line_height: 24.0,
+        }
+    }
+
+    fn set_draw_region(&mut self, region: render::FRect) {
+        self.draw_region = region;
+    }
+
+    fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    fn down(&mut self) {
+        if self.selected + 1 < self.finder.match_count() {
+            self.selected += 1;
+        }
+    }
+
+    // Absolute path of the match under the cursor.
+    fn chosen(&self) -> Option<path::PathBuf> {
+        self.finder
+            .matches()
+            .nth(self.selected)
+            .map(|p| self.finder.root.join(p))
+    }
+
+    fn render(&self, cx: &mut RenderContext) -> Result<(), Box<dyn error::Error>> {
+        let RenderContext {
+            canvas,
+            theme,
+            entity_manager,
+            text_manager,
+            texture_manager,
+            font,
+            ..
+        } = cx;
+        let draw_region = self.draw_region;
+        canvas.set_draw_color(theme.tasks);
+        let _ = canvas.fill_rect(draw_region);
+
+        let padding = 5.0;
+        let x = draw_region.x + padding;
+        let mut y = draw_region.y + padding;
+
+        let status = format!(
+            "{}/{}{} · .gitignore: {} (Tab)",
+            self.finder.match_count(),
+            self.finder.candidate_count(),
+            if self.finder.walking() {
+                " (walking…)"
+            } else if self.finder.ranking() {
+                " (ranking…)"
+            } else {
+                ""
+            },
+            if self.finder.gitignore { "on" } else { "off" },
+        );
+        let _ = texture_manager.render_uncached(
+            canvas,
+            font,
+            &format!("> {}  {}", self.finder.query(), status),
+            theme.header,
+            x,
+            y,
+        );
+        y += self.line_height + padding;
+
+        let rows = ((draw_region.y + draw_region.h - y) / self.line_height) as usize;
+        // Keep the cursor on the last row when it goes past the end.
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (index, p) in self.finder.matches().enumerate().skip(first).take(rows) {
+            if index == self.selected {
+                canvas.set_draw_color(theme.cursor);
+                let _ = canvas.fill_rect(render::FRect::new(
+                    draw_region.x,
+                    y,
+                    draw_region.w,
+                    self.line_height,
+                ));
+            }
+            let _ = text_manager.render(
+                entity_manager,
+                texture_manager,
+                canvas,
+                font,
+                &p.to_string_lossy(),
+                theme.text,
+                18,
+                x,
+                y,
+            );
+            y += self.line_height;
+        }
+
+        Ok(())
+    }
+}
+
+pub enum JobStatus {
+    Queued,
+    Interrupted {
+        files_done: usize,
+        offset: Option<u64>,
+    },
+    Running {
+        files_done: u64,
+        bytes_done: u64,
+        current: path::PathBuf,
+        strategy: iobackend::Strategy,
+    },
+    Finished(jobs::Outcome),
+}
+
 pub struct JobView {
     job: jobs::Job,
+    status: JobStatus,
+    errors: Vec<jobs::FileError>,
+    // Files and bytes the job will transfer in total, once known.
+    totals: Option<(u64, u64)>,
+    throughput: Option<progress::Throughput>,
 }
 
 pub struct JobsView {
     line_height: usize,
     jobs: Vec<JobView>,
+    prompt: Option<String>,
+    error_policy: jobs::ErrorPolicy,
+    collision_policy: jobs::CollisionPolicy,
+    symlink_policy: jobs::SymlinkPolicy,
+    verify: bool,
+    preserve: jobs::Preserve,
+    max_error_lines: usize,
 }
 
 impl JobsView {
     pub fn new() -> JobsView {
-        let job = jobs::Job {
-            id: uuidv7::create(),
-            params: jobs::JobParams::Copy(jobs::CopyParams {
-                src: std::path::absolute("main.rs").unwrap(),
-                dst: std::path::absolute("garbage.bin").unwrap(),
-            }),
-        };
         JobsView {
             line_height: 18,
-            jobs: vec![JobView { job: job }],
+            jobs: vec![],
+            prompt: None,
+            error_policy: jobs::ErrorPolicy::Abort,
+            collision_policy: jobs::CollisionPolicy::Ask,
+            symlink_policy: jobs::SymlinkPolicy::CopyLink,
+            verify: false,
+            preserve: jobs::Preserve::basic(),
+            max_error_lines: 3,
+        }
+    }
+
+    pub fn add(&mut self, job: jobs::Job, status: JobStatus) {
+        self.jobs.push(JobView {
+            job,
+            status,
+            errors: vec![],
+            totals: None,
+            throughput: None,
+        });
+    }
+
+    pub fn add_error(&mut self, job_id: &str, error: jobs::FileError) {
+        if let Some(jv) = self.jobs.iter_mut().find(|jv| jv.job.id == job_id) {
+            jv.errors.push(error);
         }
     }
 
+    pub fn set_error_policy(&mut self, error_policy: jobs::ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    pub fn set_collision_policy(&mut self, collision_policy: jobs::CollisionPolicy) {
+        self.collision_policy = collision_policy;
+    }
+
+    pub fn set_symlink_policy(&mut self, symlink_policy: jobs::SymlinkPolicy) {
+        self.symlink_policy = symlink_policy;
+    }
+
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    pub fn set_preserve(&mut self, preserve: jobs::Preserve) {
+        self.preserve = preserve;
+    }
+
+    pub fn remove(&mut self, job_id: &str) {
+        self.jobs.retain(|jv| jv.job.id != job_id);
+    }
+
+    pub fn set_status(&mut self, job_id: &str, status: JobStatus) {
+        if let Some(jv) = self.jobs.iter_mut().find(|jv| jv.job.id == job_id) {
+            if let JobStatus::Running { bytes_done, .. } = status {
+                let now = time::Instant::now();
+                match &mut jv.throughput {
+                    Some(throughput) => throughput.record(now, bytes_done),
+                    None => jv.throughput = Some(progress::Throughput::new(now, bytes_done)),
+                }
+            }
+            jv.status = status;
+        }
+    }
+
+    pub fn set_totals(&mut self, job_id: &str, files: u64, bytes: u64) {
+        if let Some(jv) = self.jobs.iter_mut().find(|jv| jv.job.id == job_id) {
+            jv.totals = Some((files, bytes));
+        }
+    }
+
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.prompt = prompt;
+    }
+
     fn render(
         &self,
-        canvas: &mut render::Canvas<video::Window>,
+        cx: &mut RenderContext,
         draw_region: render::FRect,
-        theme: &Theme,
-        entity_manager: &mut EntityManager,
-        text_manager: &mut TextManager,
-        texture_manager: &mut TextureManager,
-        font: &sdl3::ttf::Font,
     ) -> Result<(), Box<dyn error::Error>> {
+        let RenderContext {
+            canvas,
+            theme,
+            entity_manager,
+            text_manager,
+            texture_manager,
+            font,
+            ..
+        } = cx;
         canvas.set_draw_color(theme.tasks);
         let _ = canvas.fill_rect(draw_region);
 
-        for idx in 0..self.jobs.len() {
-            let job = &self.jobs[idx].job;
+        let row_height = (self.line_height + 6) as f32;
+        let mut y = draw_region.y;
+        let now = time::Instant::now();
+
+        let policy_text = match self.error_policy {
+            jobs::ErrorPolicy::Abort => String::from("abort"),
+            jobs::ErrorPolicy::Skip => String::from("skip"),
+            jobs::ErrorPolicy::Retry { attempts } => format!("retry {}x", attempts),
+        };
+        let collision_text = match self.collision_policy {
+            jobs::CollisionPolicy::Overwrite => "overwrite",
+            jobs::CollisionPolicy::Skip => "skip",
+            jobs::CollisionPolicy::OverwriteIfNewer => "overwrite if newer",
+            jobs::CollisionPolicy::OverwriteIfDifferentSize => "overwrite if different size",
+            jobs::CollisionPolicy::Rename => "rename",
+            jobs::CollisionPolicy::Ask => "ask",
+        };
+        let symlink_text = match self.symlink_policy {
+            jobs::SymlinkPolicy::CopyLink => "copy as links",
+            jobs::SymlinkPolicy::Dereference => "follow",
+            jobs::SymlinkPolicy::DereferenceOutside => "follow if outside",
+        };
+        let _ = text_manager.render(
+            entity_manager,
+            texture_manager,
+            canvas,
+            font,
+            &format!(
+                "New jobs on error: {} (F2), if the target exists: {} (F3), verify: {} (F4), keep: {} (F7), symlinks: {} (F8)",
+                policy_text,
+                collision_text,
+                if self.verify { "on" } else { "off" },
+                if self.preserve == jobs::Preserve::archive() {
+                    "all attributes"
+                } else {
+                    "mode"
+                },
+                symlink_text
+            ),
+            theme.header,
+            self.line_height,
+            draw_region.x,
+            y,
+        );
+        y += row_height;
+
+        if let Some(prompt) = &self.prompt {
+            let _ = text_manager.render(
+                entity_manager,
+                texture_manager,
+                canvas,
+                font,
+                prompt,
+                theme.header,
+                self.line_height,
+                draw_region.x,
+                y,
+            );
+            y += row_height;
+        }
+
+        for jv in self.jobs.iter() {
+            let job = &jv.job;
+            let status_text = match &jv.status {
+                JobStatus::Queued => String::from("queued"),
+                JobStatus::Interrupted {
+                    files_done,
+                    offset: Some(offset),
+                } => format!("interrupted, {} files done, at byte {}", files_done, offset),
+                JobStatus::Interrupted {
+                    files_done,
+                    offset: None,
+                } => format!("interrupted, {} files done", files_done),
+                JobStatus::Running { .. } => String::from("running"),
+                JobStatus::Finished(jobs::Outcome::Completed) => String::from("done"),
+                JobStatus::Finished(jobs::Outcome::Failed(reason)) => {
+                    format!("failed: {}", reason)
+                }
+                JobStatus::Finished(jobs::Outcome::Cancelled) => String::from("cancelled"),
+            };
             let job_text = match &job.params {
-                jobs::JobParams::Copy(jobs::CopyParams { src, dst }) => {
-                    format!("Job#{} = from {:#?} to {:#?}", job.id, src, dst)
+                jobs::JobParams::Copy(jobs::CopyParams { src, dst, .. }) => {
+                    format!(
+                        "Job#{} [{}] = copy {} to {}",
+                        job.id,
+                        status_text,
+                        src.display(),
+                        dst.display()
+                    )
+                }
+                jobs::JobParams::Move(jobs::MoveParams { src, dst, .. }) => {
+                    format!(
+                        "Job#{} [{}] = move {} to {}",
+                        job.id,
+                        status_text,
+                        src.display(),
+                        dst.display()
+                    )
+                }
+                jobs::JobParams::Delete(jobs::DeleteParams { path, mode }) => {
+                    format!(
+                        "Job#{} [{}] = {} {}",
+                        job.id,
+                        status_text,
+                        match mode {
+                            jobs::DeleteMode::Trash => "trash",
+                            jobs::DeleteMode::Permanent => "delete",
+                        },
+                        path.display()
+                    )
                 }
             };
 
@@ -483,14 +1443,124 @@ impl JobsView {
                 theme.task_text,
                 self.line_height,
                 draw_region.x,
-                draw_region.y,
+                y,
             );
+            y += row_height;
+
+            if let Some((bytes_done, bytes_text, details_text)) = progress_text(jv, now) {
+                // Bytes in the colour of their magnitude. Both change with
+                // every update, so neither is cached.
+                let _ = texture_manager.render_uncached(
+                    canvas,
+                    font,
+                    &bytes_text,
+                    theme.magnitude(bytes_done),
+                    draw_region.x,
+                    y,
+                );
+                let bytes_width = font.size_of(&bytes_text).map_or(0, |(w, _)| w) as f32;
+                let _ = texture_manager.render_uncached(
+                    canvas,
+                    font,
+                    &details_text,
+                    theme.task_text,
+                    draw_region.x + bytes_width,
+                    y,
+                );
+
+                // Throughput sparkline over the last minute, newest on the right.
+                if let Some(throughput) = &jv.throughput {
+                    let history = throughput.history(now);
+                    let peak = history.iter().copied().max().unwrap_or(0).max(1);
+                    let height = self.line_height as f32;
+                    let right = draw_region.x + draw_region.w - 10.0;
+                    for (i, bytes) in history.iter().rev().enumerate() {
+                        let bar_height = (*bytes as f32 / peak as f32 * height).max(1.0);
+                        canvas.set_draw_color(theme.magnitude(*bytes));
+                        let _ = canvas.fill_rect(render::FRect::new(
+                            right - (i + 1) as f32 * 4.0,
+                            y + height - bar_height,
+                            3.0,
+                            bar_height,
+                        ));
+                    }
+                }
+                y += row_height;
+            }
+
+            let mut error_lines: Vec<String> = jv
+                .errors
+                .iter()
+                .take(self.max_error_lines)
+                .map(|e| format!("  ! {}: {}", e.path.display(), e.error))
+                .collect();
+            if jv.errors.len() > self.max_error_lines {
+                error_lines.push(format!(
+                    "  ! ...and {} more",
+                    jv.errors.len() - self.max_error_lines
+                ));
+            }
+            for line in error_lines {
+                let _ = text_manager.render(
+                    entity_manager,
+                    texture_manager,
+                    canvas,
+                    font,
+                    &line,
+                    theme.error,
+                    self.line_height,
+                    draw_region.x,
+                    y,
+                );
+                y += row_height;
+            }
         }
 
         Ok(())
     }
 }
 
+// The line under a running job: transferred/total bytes, and then file count,
+// rate, ETA and current file.
+fn progress_text(jv: &JobView, now: time::Instant) -> Option<(u64, String, String)> {
+    let JobStatus::Running {
+        files_done,
+        bytes_done,
+        current,
+        strategy,
+    } = &jv.status
+    else {
+        return None;
+    };
+    let rate = jv.throughput.as_ref().map_or(0.0, |t| t.rate(now));
+
+    let bytes_text = match jv.totals {
+        Some((_, bytes_total)) => format!(
+            "  {} / {}",
+            progress::format_bytes(*bytes_done),
+            progress::format_bytes(bytes_total)
+        ),
+        None => format!("  {}", progress::format_bytes(*bytes_done)),
+    };
+    let files_text = match jv.totals {
+        Some((files_total, _)) => format!("{}/{} files", files_done, files_total),
+        None => format!("{} files", files_done),
+    };
+    let eta_text = jv
+        .totals
+        .and_then(|(_, bytes_total)| progress::eta(bytes_total.saturating_sub(*bytes_done), rate))
+        .map_or(String::from("--:--"), progress::format_duration);
+    let details_text = format!(
+        " · {} · {}/s · ETA {} · {} ({})",
+        files_text,
+        progress::format_bytes(rate as u64),
+        eta_text,
+        current.display(),
+        strategy.name()
+    );
+    Some((*bytes_done, bytes_text, details_text))
+}
+
 enum Side {
     Left,
     Right,
@@ -498,7 +1568,7 @@ enum Side {
 
 enum DirectoryViewState {
     Active,
-    Inactive(DirectoryView),
+    Inactive(Box<DirectoryView>),
 }
 
 pub struct UI<'ui> {
@@ -513,27 +1583,34 @@ pub struct UI<'ui> {
     lhs: DirectoryView,
     rhs: DirectoryView,
     jobs_view: JobsView,
+    // Index into COLUMN_SETS.
+    column_set: usize,
+    // Sort orders picked for directories, applied whenever they are shown.
+    sort_orders: collections::HashMap<path::PathBuf, directory::SortOrder>,
+    finder_view: Option<FinderView>,
+    // None if inotify isn't available; the views just don't update then.
+    watcher: Option<watcher::Watcher>,
 }
 
 impl<'ui> UI<'ui> {
     pub fn new(
         texture_creator: &'static render::TextureCreator<video::WindowContext>,
         font: &'ui sdl3::ttf::Font,
-        left_entries: directory::Entries,
-        right_entries: directory::Entries,
+        left_loading: directory::Loading,
+        right_loading: directory::Loading,
     ) -> UI<'ui> {
         let mut left_directory_views = collections::HashMap::new();
         left_directory_views.insert(
-            left_entries.absolute_path.clone(),
+            left_loading.absolute_path.clone(),
             DirectoryViewState::Active,
         );
         let mut right_directory_views = collections::HashMap::new();
         right_directory_views.insert(
-            right_entries.absolute_path.clone(),
+            right_loading.absolute_path.clone(),
             DirectoryViewState::Active,
         );
 
-        let mut ui = UI {
+        UI {
             left_directory_views: left_directory_views,
             right_directory_views: right_directory_views,
             theme: Theme::default(),
@@ -542,21 +1619,206 @@ impl<'ui> UI<'ui> {
             texture_manager: TextureManager::new(texture_creator),
             active: Side::Left,
             font: font,
-            lhs: DirectoryView::from(&left_entries),
-            rhs: DirectoryView::from(&right_entries),
+            lhs: DirectoryView::from(left_loading),
+            rhs: DirectoryView::from(right_loading),
             jobs_view: JobsView::new(),
+            column_set: 0,
+            sort_orders: collections::HashMap::new(),
+            finder_view: None,
+            watcher: watcher::Watcher::new()
+                .map_err(|err| eprintln!("Directory watching err={}", err))
+                .ok(),
+        }
+    }
+
+    pub fn cycle_columns(&mut self) {
+        self.column_set = (self.column_set + 1) % COLUMN_SETS.len();
+    }
+
+    fn active_directory_view_mut(&mut self) -> &mut DirectoryView {
+        match self.active {
+            Side::Left => &mut self.lhs,
+            Side::Right => &mut self.rhs,
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.active_directory_view_mut().start_search();
+    }
+
+    pub fn edit_search(&mut self, edit: impl FnOnce(&mut String)) {
+        self.active_directory_view_mut().edit_search(edit);
+    }
+
+    pub fn commit_search(&mut self) {
+        self.active_directory_view_mut().commit_search();
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.active_directory_view_mut().cancel_search();
+    }
+
+    pub fn next_match(&mut self, forward: bool) {
+        self.active_directory_view_mut().next_match(forward);
+    }
+
+    // Starts finding below the active directory.
+    pub fn open_finder(&mut self, gitignore: bool) {
+        let root = self.active_dir_path();
+        self.finder_view = Some(FinderView::new(finder::Finder::new(root, gitignore)));
+    }
+
+    pub fn close_finder(&mut self) {
+        self.finder_view = None;
+    }
+
+    pub fn poll_finder(&mut self) {
+        if let Some(fv) = &mut self.finder_view {
+            fv.finder.poll();
+        }
+    }
+
+    pub fn edit_finder_query(&mut self, edit: impl FnOnce(&mut String)) {
+        if let Some(fv) = &mut self.finder_view {
+            fv.finder.edit_query(edit);
+            fv.selected = 0;
+        }
+    }
+
+    // Walks the tree again, with or without the .gitignore files, keeping the
+    // query.
+    pub fn toggle_finder_gitignore(&mut self) {
+        if let Some(fv) = &mut self.finder_view {
+            let query = fv.finder.query().to_string();
+            let mut finder = finder::Finder::new(fv.finder.root.clone(), !fv.finder.gitignore);
+            finder.edit_query(|q| *q = query);
+            *fv = FinderView::new(finder);
+        }
+    }
+
+    pub fn finder_up(&mut self) {
+        if let Some(fv) = &mut self.finder_view {
+            fv.up();
+        }
+    }
+
+    pub fn finder_down(&mut self) {
+        if let Some(fv) = &mut self.finder_view {
+            fv.down();
+        }
+    }
+
+    pub fn finder_choice(&self) -> Option<path::PathBuf> {
+        self.finder_view.as_ref().and_then(|fv| fv.chosen())
+    }
+
+    // Puts the cursor of the active view on the entry called `name`.
+    pub fn hover_name(&mut self, name: &path::Path) {
+        self.active_directory_view_mut().hover_name(name);
+    }
+
+    // Changes the sort order of the active view and remembers it for its
+    // directory.
+    pub fn change_sort(&mut self, change: impl FnOnce(&mut directory::SortOrder)) {
+        let dv = match self.active {
+            Side::Left => &mut self.lhs,
+            Side::Right => &mut self.rhs,
         };
-        ui.lhs.selected_index = Some(0);
-        ui.rhs.selected_index = Some(0);
-        ui
+        let mut sort = dv.sort;
+        change(&mut sort);
+        dv.set_sort(sort);
+        self.sort_orders.insert(dv.dir.clone(), sort);
+    }
+
+    fn active_directory_views(
+        &mut self,
+    ) -> &mut collections::HashMap<path::PathBuf, DirectoryViewState> {
+        match self.active {
+            Side::Left => &mut self.left_directory_views,
+            Side::Right => &mut self.right_directory_views,
+        }
     }
 
-    pub fn update_dir_entries(&mut self, de: directory::Entries) {
-        let left_e = self.left_directory_views.entry(de.absolute_path.clone());
-        left_e.or_insert(DirectoryViewState::Inactive(DirectoryView::from(&de)));
+    // Whether the active side has a view of `abs_path`, loaded or loading.
+    pub fn has_dir(&mut self, abs_path: &path::Path) -> bool {
+        self.active_directory_views().contains_key(abs_path)
+    }
 
-        let right_e = self.right_directory_views.entry(de.absolute_path.clone());
-        right_e.or_insert(DirectoryViewState::Inactive(DirectoryView::from(&de)));
+    // Adds a view for the active side that fills up as `loading` reads the
+    // directory.
+    pub fn load_dir(&mut self, loading: directory::Loading) {
+        let abs_path = loading.absolute_path.clone();
+        let mut dv = DirectoryView::from(loading);
+        if let Some(sort) = self.sort_orders.get(&abs_path) {
+            dv.sort = *sort;
+        }
+        self.active_directory_views()
+            .entry(abs_path)
+            .or_insert(DirectoryViewState::Inactive(Box::new(dv)));
+    }
+
+    // Keeps the visible directories in step with what is on disk.
+    pub fn poll_changes(&mut self) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        watcher.watch_only(&[&self.lhs.dir, &self.rhs.dir]);
+        let mut changed: collections::HashMap<path::PathBuf, collections::HashSet<path::PathBuf>> =
+            collections::HashMap::new();
+        let mut gone = vec![];
+        for change in watcher.changes() {
+            match change {
+                watcher::Change::Entry { dir, name } => {
+                    changed.entry(dir).or_default().insert(name);
+                }
+                watcher::Change::DirGone { dir } => gone.push(dir),
+                watcher::Change::Overflow => {
+                    eprintln!("Missed directory changes, refreshing");
+                    self.lhs.refresh();
+                    self.rhs.refresh();
+                }
+            }
+        }
+        for dv in [&mut self.lhs, &mut self.rhs] {
+            if let Some(names) = changed.get(&dv.dir) {
+                dv.apply_changes(names);
+            }
+        }
+        for dir in gone {
+            self.leave_gone_dir(&dir);
+        }
+    }
+
+    // Moves the panes showing `dir`, which was deleted or moved away, to the
+    // nearest directory above it that still exists.
+    fn leave_gone_dir(&mut self, dir: &path::Path) {
+        let Some(parent) = dir.ancestors().skip(1).find(|p| p.is_dir()) else {
+            return;
+        };
+        eprintln!("{} is gone, showing {}", dir.display(), parent.display());
+        // Both sides in turn, ending up on the one that was active.
+        for _ in 0..2 {
+            if self.active_directory_view_mut().dir == dir {
+                if !self.has_dir(parent) {
+                    match directory::Loading::open(parent.to_path_buf()) {
+                        Ok(loading) => self.load_dir(loading),
+                        Err(err) => eprintln!("Read dir {} err={}", parent.display(), err),
+                    }
+                }
+                self.show_dir(parent.to_path_buf(), path::PathBuf::new());
+                self.active_directory_views().remove(dir);
+            }
+            self.toggle_side();
+        }
+    }
+
+    pub fn refresh(&mut self) {
+        self.active_directory_view_mut().refresh();
+    }
+
+    pub fn poll_loading(&mut self) {
+        self.lhs.poll_loading();
+        self.rhs.poll_loading();
     }
 
     pub fn up(&mut self, distance: usize) {
@@ -612,6 +1874,61 @@ impl<'ui> UI<'ui> {
         self.active_directory_view().dir.clone()
     }
 
+    pub fn inactive_dir_path(&self) -> path::PathBuf {
+        match self.active {
+            Side::Left => self.rhs.dir.clone(),
+            Side::Right => self.lhs.dir.clone(),
+        }
+    }
+
+    pub fn selected_paths(&self) -> Vec<path::PathBuf> {
+        self.active_directory_view().selected_paths()
+    }
+
+    pub fn add_job(&mut self, job: jobs::Job, status: JobStatus) {
+        self.jobs_view.add(job, status);
+    }
+
+    pub fn remove_job(&mut self, job_id: &str) {
+        self.jobs_view.remove(job_id);
+    }
+
+    pub fn set_job_status(&mut self, job_id: &str, status: JobStatus) {
+        self.jobs_view.set_status(job_id, status);
+    }
+
+    pub fn set_job_totals(&mut self, job_id: &str, files: u64, bytes: u64) {
+        self.jobs_view.set_totals(job_id, files, bytes);
+    }
+
+    pub fn add_job_error(&mut self, job_id: &str, error: jobs::FileError) {
+        self.jobs_view.add_error(job_id, error);
+    }
+
+    pub fn set_error_policy(&mut self, error_policy: jobs::ErrorPolicy) {
+        self.jobs_view.set_error_policy(error_policy);
+    }
+
+    pub fn set_collision_policy(&mut self, collision_policy: jobs::CollisionPolicy) {
+        self.jobs_view.set_collision_policy(collision_policy);
+    }
+
+    pub fn set_symlink_policy(&mut self, symlink_policy: jobs::SymlinkPolicy) {
+        self.jobs_view.set_symlink_policy(symlink_policy);
+    }
+
+    pub fn set_verify(&mut self, verify: bool) {
+        self.jobs_view.set_verify(verify);
+    }
+
+    pub fn set_preserve(&mut self, preserve: jobs::Preserve) {
+        self.jobs_view.set_preserve(preserve);
+    }
+
+    pub fn set_jobs_prompt(&mut self, prompt: Option<String>) {
+        self.jobs_view.set_prompt(prompt);
+    }
+
     pub fn hovered_entry(&self) -> Option<directory::Entry> {
         let dv = self.active_directory_view();
         dv.hovered_entry()
@@ -626,50 +1943,41 @@ impl<'ui> UI<'ui> {
             Side::Left => &mut self.lhs,
             Side::Right => &mut self.rhs,
         };
-        if let Some(dvs) = side_directory_views.get(&abs_path) {
-            match dvs {
-                DirectoryViewState::Active => {
-                    // this means we received new dir entries for the currently shown dv
-                    eprintln!("Active? for {}", abs_path.display());
+        match side_directory_views.remove(&abs_path) {
+            // Already shown, and kept up to date by poll_changes.
+            Some(DirectoryViewState::Active) => {
+                side_directory_views.insert(abs_path, DirectoryViewState::Active);
+            }
+            Some(DirectoryViewState::Inactive(active_dv)) => {
+                side_directory_views.insert(abs_path, DirectoryViewState::Active);
+                let mut old_dv = mem::replace(side, *active_dv);
+                // The watcher only follows what is on screen, so a cached view
+                // may be out of date.
+                side.refresh();
+                if side.selected_index.is_none() {
+                    side.hover_name(&selected_entry);
                 }
-                DirectoryViewState::Inactive(_) => {
-                    eprintln!("Inactive? for {}", abs_path.display());
-                    if let Some(prev) = side_directory_views.remove(&abs_path) {
-                        side_directory_views.insert(abs_path.clone(), DirectoryViewState::Active);
-                        match prev {
-                            DirectoryViewState::Inactive(active_dv) => {
-                                let mut selected_index = active_dv.selected_index;
-                                if active_dv.selected_index.is_none() {
-                                    for (idx, entry) in active_dv.entries.iter().enumerate() {
-                                        if entry.entry.name == selected_entry {
-                                            selected_index = Some(idx);
-                                            break;
-                                        }
-                                    }
-                                }
-                                if selected_index.is_none() && active_dv.entries.len() > 0 {
-                                    selected_index = Some(0)
-                                }
-
-                                let old_dv = mem::replace(side, active_dv);
-                                side.selected_index = selected_index;
-                                side_directory_views.insert(
-                                    old_dv.dir.clone(),
-                                    DirectoryViewState::Inactive(old_dv),
-                                );
-                            }
-                            _ => {
-                                eprintln!("This should not happen ....");
-                            }
-                        }
-                    }
+                if side.selected_index.is_none() && !side.entries.is_empty() {
+                    side.selected_index = Some(0)
+                }
+
+                if old_dv.loading.is_some() {
+                    // Dropping it stops the loading; the next visit starts
+                    // over instead of showing half a directory.
+                    side_directory_views.remove(&old_dv.dir);
+                } else {
+                    // Done on the next visit anyway.
+                    old_dv.refresh = None;
+                    side_directory_views.insert(
+                        old_dv.dir.clone(),
+                        DirectoryViewState::Inactive(Box::new(old_dv)),
+                    );
                 }
             }
-        } else {
-            eprintln!(
+            None => eprintln!(
                 "Trying to show dir without entries...{}",
                 abs_path.display()
-            );
+            ),
         }
     }
 
@@ -695,38 +2003,33 @@ impl<'ui> UI<'ui> {
             Side::Left => false,
             Side::Right => true,
         };
-        let _ = self.lhs.render(
+        let mut cx = RenderContext {
             canvas,
-            &self.theme,
-            &mut self.entity_manager,
-            &mut self.text_manager,
-            &mut self.texture_manager,
-            left_active,
-            self.font,
-        );
+            theme: &self.theme,
+            entity_manager: &mut self.entity_manager,
+            text_manager: &mut self.text_manager,
+            texture_manager: &mut self.texture_manager,
+            font: self.font,
+            columns: COLUMN_SETS[self.column_set],
+        };
+        let _ = self.lhs.render(&mut cx, left_active);
         let right_region = render::FRect::new(ww / 2.0, 0.0, ww / 2.0, hh - 200.0);
         self.rhs.set_draw_region(right_region);
-        let _ = self.rhs.render(
-            canvas,
-            &self.theme,
-            &mut self.entity_manager,
-            &mut self.text_manager,
-            &mut self.texture_manager,
-            right_active,
-            self.font,
-        );
+        let _ = self.rhs.render(&mut cx, right_active);
 
         let tasks_region = render::FRect::new(0.0, hh - 200.0, ww, 200.0);
-        let _ = self.jobs_view.render(
-            canvas,
-            tasks_region,
-            &self.theme,
-            &mut self.entity_manager,
-            &mut self.text_manager,
-            &mut self.texture_manager,
-            self.font,
-        );
+        let _ = self.jobs_view.render(&mut cx, tasks_region);
+
+        if let Some(fv) = &mut self.finder_view {
+            fv.set_draw_region(render::FRect::new(
+                ww / 6.0,
+                40.0,
+                ww * 2.0 / 3.0,
+                hh - 300.0,
+            ));
+            let _ = fv.render(&mut cx);
+        }
 
-        canvas.present();
+        cx.canvas.present();
     }
 }
@@ -0,0 +1,932 @@
+use std::collections;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path;
+
+use crate::jobs;
+
+// File header. Everything after it is a sequence of frames:
+// [payload len: u32 LE][fnv1a(payload): u32 LE][payload]
+// A payload is a record tag followed by (field, len: u32 LE, value) triples so
+// fields can be added later without breaking replay of older logs.
+const MAGIC: &[u8; 8] = b"FPWAL\0\0\x01";
+const FRAME_HEADER_LEN: usize = 8;
+
+const RECORD_CREATED: u8 = 1;
+const RECORD_FILE_STARTED: u8 = 2;
+const RECORD_CHECKPOINT: u8 = 3;
+const RECORD_FILE_DONE: u8 = 4;
+const RECORD_FINISHED: u8 = 5;
+const RECORD_SKIPPED: u8 = 6;
+const RECORD_KEPT: u8 = 7;
+
+const FIELD_JOB_ID: u8 = 1;
+const FIELD_KIND: u8 = 2;
+const FIELD_SRC: u8 = 3;
+const FIELD_DST: u8 = 4;
+const FIELD_OFFSET: u8 = 5;
+const FIELD_BYTES: u8 = 6;
+const FIELD_OUTCOME: u8 = 7;
+const FIELD_REASON: u8 = 8;
+const FIELD_DELETE_MODE: u8 = 9;
+const FIELD_ERROR_POLICY: u8 = 10;
+const FIELD_ATTEMPTS: u8 = 11;
+const FIELD_COLLISION_POLICY: u8 = 12;
+const FIELD_VERIFY: u8 = 13;
+const FIELD_PRESERVE: u8 = 14;
+const FIELD_SYMLINK_POLICY: u8 = 15;
+
+const KIND_COPY: u8 = 1;
+const KIND_MOVE: u8 = 2;
+const KIND_DELETE: u8 = 3;
+
+const DELETE_MODE_TRASH: u8 = 1;
+const DELETE_MODE_PERMANENT: u8 = 2;
+
+const ERROR_POLICY_ABORT: u8 = 1;
+const ERROR_POLICY_SKIP: u8 = 2;
+const ERROR_POLICY_RETRY: u8 = 3;
+
+const COLLISION_POLICY_OVERWRITE: u8 = 1;
+const COLLISION_POLICY_SKIP: u8 = 2;
+const COLLISION_POLICY_IF_NEWER: u8 = 3;
+const COLLISION_POLICY_IF_DIFFERENT_SIZE: u8 = 4;
+const COLLISION_POLICY_RENAME: u8 = 5;
+const COLLISION_POLICY_ASK: u8 = 6;
+
+const SYMLINK_POLICY_COPY_LINK: u8 = 1;
+const SYMLINK_POLICY_DEREFERENCE: u8 = 2;
+const SYMLINK_POLICY_DEREFERENCE_OUTSIDE: u8 = 3;
+
+const OUTCOME_COMPLETED: u8 = 1;
+const OUTCOME_FAILED: u8 = 2;
+const OUTCOME_CANCELLED: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    FileStarted {
+        src: path::PathBuf,
+        dst: path::PathBuf,
+    },
+    Checkpoint {
+        src: path::PathBuf,
+        dst: path::PathBuf,
+        offset: u64,
+    },
+    FileDone {
+        src: path::PathBuf,
+        dst: path::PathBuf,
+        bytes: u64,
+    },
+    Skipped(jobs::FileError),
+    // Left alone because something was already at its destination.
+    Kept(path::PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Created(jobs::Job),
+    Step {
+        job_id: String,
+        step: Step,
+    },
+    Finished {
+        job_id: String,
+        outcome: jobs::Outcome,
+    },
+}
+
+impl Record {
+    pub fn job_id(&self) -> &str {
+        match self {
+            Record::Created(job) => &job.id,
+            Record::Step { job_id, .. } => job_id,
+            Record::Finished { job_id, .. } => job_id,
+        }
+    }
+}
+
+// Jobs that were created but never finished, oldest first (uuidv7 ids sort by
+// creation time), along with how far each one got.
+pub fn in_flight(records: &[Record]) -> Vec<(jobs::Job, jobs::Resume)> {
+    let mut pending: collections::HashMap<String, (jobs::Job, jobs::Resume)> =
+        collections::HashMap::new();
+
+    for record in records {
+        match record {
+            Record::Created(job) => {
+                pending.insert(job.id.clone(), (job.clone(), jobs::Resume::default()));
+            }
+            Record::Step { job_id, step } => {
+                let Some((_, resume)) = pending.get_mut(job_id) else {
+                    continue;
+                };
+                match step {
+                    Step::FileStarted { src, dst } => {
+                        resume.targets.insert(src.clone(), dst.clone());
+                    }
+                    Step::Checkpoint { src, dst, offset } => {
+                        resume.checkpoint = Some(jobs::Checkpoint {
+                            src: src.clone(),
+                            dst: dst.clone(),
+                            offset: *offset,
+                        });
+                    }
+                    Step::FileDone { src, dst, .. } => {
+                        if resume.checkpoint.as_ref().is_some_and(|c| &c.src == src) {
+                            resume.checkpoint = None;
+                        }
+                        resume.done.insert(src.clone());
+                        resume.targets.insert(src.clone(), dst.clone());
+                    }
+                    Step::Skipped(error) => resume.errors.push(error.clone()),
+                    Step::Kept(src) => {
+                        resume.kept.insert(src.clone());
+                    }
+                }
+            }
+            Record::Finished { job_id, .. } => {
+                pending.remove(job_id);
+            }
+        }
+    }
+
+    let mut pending: Vec<(jobs::Job, jobs::Resume)> = pending.into_values().collect();
+    pending.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+    pending
+}
+
+pub struct Wal {
+    path: path::PathBuf,
+    file: fs::File,
+}
+
+impl Wal {
+    // $XDG_STATE_HOME/files_please/jobs.wal, falling back to ~/.local/state.
+    pub fn default_path() -> Option<path::PathBuf> {
+        let state_dir = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+            _ => path::PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+        };
+        Some(state_dir.join("files_please").join("jobs.wal"))
+    }
+
+    // Opens (or creates) the log and returns every durable record in it. A torn
+    // frame at the tail (crash mid-append) is cut off so new appends follow the
+    // last good record.
+    pub fn open(wal_path: &path::Path) -> io::Result<(Wal, Vec<Record>)> {
+        let mut created = false;
+        if let Some(parent) = wal_path.parent() {
+            fs::create_dir_all(parent)?;
+            created = !wal_path.exists();
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(wal_path)?;
+
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+
+        if buf.len() < MAGIC.len() && MAGIC.starts_with(&buf) {
+            file.set_len(0)?;
+            file.seek(io::SeekFrom::Start(0))?;
+            file.write_all(MAGIC)?;
+            file.sync_all()?;
+            buf = MAGIC.to_vec();
+        }
+        if created && let Some(parent) = wal_path.parent() {
+            fs::File::open(parent)?.sync_all()?;
+        }
+
+        if !buf.starts_with(MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a job log", wal_path.display()),
+            ));
+        }
+
+        let (records, valid_len) = decode_frames(&buf[MAGIC.len()..]);
+        let valid_len = (MAGIC.len() + valid_len) as u64;
+        if valid_len < buf.len() as u64 {
+            eprintln!(
+                "WAL: discarding {} bytes of torn tail in {}",
+                buf.len() as u64 - valid_len,
+                wal_path.display()
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        file.seek(io::SeekFrom::Start(valid_len))?;
+
+        Ok((
+            Wal {
+                path: wal_path.to_path_buf(),
+                file,
+            },
+            records,
+        ))
+    }
+
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+
+    // Atomically replaces the log with just `keep`, dropping the history of
+    // finished jobs so the log doesn't grow forever.
+    pub fn compact(&mut self, keep: &[Record]) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("wal.tmp");
+        {
+            let mut tmp = fs::File::create(&tmp_path)?;
+            tmp.write_all(MAGIC)?;
+            for record in keep {
+                tmp.write_all(&encode_frame(record))?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        if let Some(parent) = self.path.parent() {
+            fs::File::open(parent)?.sync_all()?;
+        }
+        self.file = fs::OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    // Returns once the record is on stable storage.
+    pub fn append(&mut self, record: &Record) -> io::Result<()> {
+        self.file.write_all(&encode_frame(record))?;
+        self.file.sync_data()
+    }
+
+    // Appends several records with a single sync.
+    pub fn append_all(&mut self, records: &[Record]) -> io::Result<()> {
+        let frames: Vec<u8> = records.iter().flat_map(encode_frame).collect();
+        self.file.write_all(&frames)?;
+        self.file.sync_data()
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for b in bytes {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn new(tag: u8) -> Encoder {
+        Encoder { buf: vec![tag] }
+    }
+
+    fn bytes(&mut self, field: u8, value: &[u8]) {
+        self.buf.push(field);
+        self.buf.extend((value.len() as u32).to_le_bytes());
+        self.buf.extend(value);
+    }
+
+    fn str(&mut self, field: u8, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn path(&mut self, field: u8, value: &path::Path) {
+        self.bytes(field, value.as_os_str().as_bytes());
+    }
+
+    fn u8(&mut self, field: u8, value: u8) {
+        self.bytes(field, &[value]);
+    }
+
+    fn u64(&mut self, field: u8, value: u64) {
+        self.bytes(field, &value.to_le_bytes());
+    }
+}
+
+struct Fields<'a> {
+    tag: u8,
+    fields: Vec<(u8, &'a [u8])>,
+}
+
+impl<'a> Fields<'a> {
+    fn parse(payload: &'a [u8]) -> io::Result<Fields<'a>> {
+        let (tag, mut rest) = payload
+            .split_first()
+            .ok_or_else(|| invalid_data("empty record"))?;
+        let mut fields = vec![];
+        while !rest.is_empty() {
+            if rest.len() < 5 {
+                return Err(invalid_data("truncated field header"));
+            }
+            let len = u32::from_le_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+            if rest.len() < 5 + len {
+                return Err(invalid_data("truncated field value"));
+            }
+            fields.push((rest[0], &rest[5..5 + len]));
+            rest = &rest[5 + len..];
+        }
+        Ok(Fields { tag: *tag, fields })
+    }
+
+    fn bytes(&self, field: u8) -> Option<&'a [u8]> {
+        self.fields
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, value)| *value)
+    }
+
+    fn has(&self, field: u8) -> bool {
+        self.bytes(field).is_some()
+    }
+
+    fn required(&self, field: u8) -> io::Result<&'a [u8]> {
+        self.bytes(field)
+            .ok_or_else(|| invalid_data(&format!("missing field {}", field)))
+    }
+
+    fn str(&self, field: u8) -> io::Result<String> {
+        String::from_utf8(self.required(field)?.to_vec())
+            .map_err(|_| invalid_data("field is not utf-8"))
+    }
+
+    fn path(&self, field: u8) -> io::Result<path::PathBuf> {
+        Ok(path::PathBuf::from(std::ffi::OsStr::from_bytes(
+            self.required(field)?,
+        )))
+    }
+
+    fn u8(&self, field: u8) -> io::Result<u8> {
+        match self.required(field)? {
+            [value] => Ok(*value),
+            _ => Err(invalid_data("bad u8 field")),
+        }
+    }
+
+    fn u64(&self, field: u8) -> io::Result<u64> {
+        let value: [u8; 8] = self
+            .required(field)?
+            .try_into()
+            .map_err(|_| invalid_data("bad u64 field"))?;
+        Ok(u64::from_le_bytes(value))
+    }
+}
+
+fn encode_error_policy(enc: &mut Encoder, policy: jobs::ErrorPolicy) {
+    match policy {
+        jobs::ErrorPolicy::Abort => enc.u8(FIELD_ERROR_POLICY, ERROR_POLICY_ABORT),
+        jobs::ErrorPolicy::Skip => enc.u8(FIELD_ERROR_POLICY, ERROR_POLICY_SKIP),
+        jobs::ErrorPolicy::Retry { attempts } => {
+            enc.u8(FIELD_ERROR_POLICY, ERROR_POLICY_RETRY);
+            enc.u64(FIELD_ATTEMPTS, attempts as u64);
+        }
+    }
+}
+
+// Jobs logged before error policies existed abort on the first error.
+fn decode_error_policy(fields: &Fields) -> io::Result<jobs::ErrorPolicy> {
+    if !fields.has(FIELD_ERROR_POLICY) {
+        return Ok(jobs::ErrorPolicy::Abort);
+    }
+    match fields.u8(FIELD_ERROR_POLICY)? {
+        ERROR_POLICY_ABORT => Ok(jobs::ErrorPolicy::Abort),
+        ERROR_POLICY_SKIP => Ok(jobs::ErrorPolicy::Skip),
+        ERROR_POLICY_RETRY => Ok(jobs::ErrorPolicy::Retry {
+            attempts: fields.u64(FIELD_ATTEMPTS)? as u32,
+        }),
+        policy => Err(invalid_data(&format!("unknown error policy {}", policy))),
+    }
+}
+
+fn encode_collision_policy(enc: &mut Encoder, policy: jobs::CollisionPolicy) {
+    enc.u8(
+        FIELD_COLLISION_POLICY,
+        match policy {
+            jobs::CollisionPolicy::Overwrite => COLLISION_POLICY_OVERWRITE,
+            jobs::CollisionPolicy::Skip => COLLISION_POLICY_SKIP,
+            jobs::CollisionPolicy::OverwriteIfNewer => COLLISION_POLICY_IF_NEWER,
+            jobs::CollisionPolicy::OverwriteIfDifferentSize => COLLISION_POLICY_IF_DIFFERENT_SIZE,
+            jobs::CollisionPolicy::Rename => COLLISION_POLICY_RENAME,
+            jobs::CollisionPolicy::Ask => COLLISION_POLICY_ASK,
+        },
+    );
+}
+
+// Jobs logged before collision policies existed overwrite.
+fn decode_collision_policy(fields: &Fields) -> io::Result<jobs::CollisionPolicy> {
+    if !fields.has(FIELD_COLLISION_POLICY) {
+        return Ok(jobs::CollisionPolicy::Overwrite);
+    }
+    match fields.u8(FIELD_COLLISION_POLICY)? {
+        COLLISION_POLICY_OVERWRITE => Ok(jobs::CollisionPolicy::Overwrite),
+        COLLISION_POLICY_SKIP => Ok(jobs::CollisionPolicy::Skip),
+        COLLISION_POLICY_IF_NEWER => Ok(jobs::CollisionPolicy::OverwriteIfNewer),
+        COLLISION_POLICY_IF_DIFFERENT_SIZE => Ok(jobs::CollisionPolicy::OverwriteIfDifferentSize),
+        COLLISION_POLICY_RENAME => Ok(jobs::CollisionPolicy::Rename),
+        COLLISION_POLICY_ASK => Ok(jobs::CollisionPolicy::Ask),
+        policy => Err(invalid_data(&format!(
+            "unknown collision policy {}",
+            policy
+        ))),
+    }
+}
+
+fn encode_symlink_policy(enc: &mut Encoder, policy: jobs::SymlinkPolicy) {
+    enc.u8(
+        FIELD_SYMLINK_POLICY,
+        match policy {
+            jobs::SymlinkPolicy::CopyLink => SYMLINK_POLICY_COPY_LINK,
+            jobs::SymlinkPolicy::Dereference => SYMLINK_POLICY_DEREFERENCE,
+            jobs::SymlinkPolicy::DereferenceOutside => SYMLINK_POLICY_DEREFERENCE_OUTSIDE,
+        },
+    );
+}
+
+// Jobs logged before symlink policies existed copy links as links.
+fn decode_symlink_policy(fields: &Fields) -> io::Result<jobs::SymlinkPolicy> {
+    if !fields.has(FIELD_SYMLINK_POLICY) {
+        return Ok(jobs::SymlinkPolicy::CopyLink);
+    }
+    match fields.u8(FIELD_SYMLINK_POLICY)? {
+        SYMLINK_POLICY_COPY_LINK => Ok(jobs::SymlinkPolicy::CopyLink),
+        SYMLINK_POLICY_DEREFERENCE => Ok(jobs::SymlinkPolicy::Dereference),
+        SYMLINK_POLICY_DEREFERENCE_OUTSIDE => Ok(jobs::SymlinkPolicy::DereferenceOutside),
+        policy => Err(invalid_data(&format!("unknown symlink policy {}", policy))),
+    }
+}
+
+// Jobs logged before verification existed don't verify.
+fn decode_verify(fields: &Fields) -> io::Result<bool> {
+    if !fields.has(FIELD_VERIFY) {
+        return Ok(false);
+    }
+    Ok(fields.u8(FIELD_VERIFY)? != 0)
+}
+
+const PRESERVE_MODE: u8 = 1;
+const PRESERVE_OWNERSHIP: u8 = 2;
+const PRESERVE_TIMESTAMPS: u8 = 4;
+const PRESERVE_XATTRS: u8 = 8;
+const PRESERVE_ACLS: u8 = 16;
+
+fn encode_preserve(enc: &mut Encoder, preserve: jobs::Preserve) {
+    let flags = [
+        (preserve.mode, PRESERVE_MODE),
+        (preserve.ownership, PRESERVE_OWNERSHIP),
+        (preserve.timestamps, PRESERVE_TIMESTAMPS),
+        (preserve.xattrs, PRESERVE_XATTRS),
+        (preserve.acls, PRESERVE_ACLS),
+    ];
+    let bits = flags
+        .iter()
+        .filter(|(on, _)| *on)
+        .fold(0, |bits, (_, bit)| bits | bit);
+    enc.u8(FIELD_PRESERVE, bits);
+}
+
+// Jobs logged before the option existed kept just the mode.
+fn decode_preserve(fields: &Fields) -> io::Result<jobs::Preserve> {
+    if !fields.has(FIELD_PRESERVE) {
+        return Ok(jobs::Preserve::basic());
+    }
+    let bits = fields.u8(FIELD_PRESERVE)?;
+    Ok(jobs::Preserve {
+        mode: bits & PRESERVE_MODE != 0,
+        ownership: bits & PRESERVE_OWNERSHIP != 0,
+        timestamps: bits & PRESERVE_TIMESTAMPS != 0,
+        xattrs: bits & PRESERVE_XATTRS != 0,
+        acls: bits & PRESERVE_ACLS != 0,
+    })
+}
+
+fn encode_job(enc: &mut Encoder, job: &jobs::Job) {
+    enc.str(FIELD_JOB_ID, &job.id);
+    match &job.params {
+        jobs::JobParams::Copy(params) => {
+            enc.u8(FIELD_KIND, KIND_COPY);
+            enc.path(FIELD_SRC, &params.src);
+            enc.path(FIELD_DST, &params.dst);
+            encode_error_policy(enc, params.error_policy);
+            encode_collision_policy(enc, params.collision_policy);
+            encode_symlink_policy(enc, params.symlink_policy);
+            enc.u8(FIELD_VERIFY, params.verify as u8);
+            encode_preserve(enc, params.preserve);
+        }
+        jobs::JobParams::Move(params) => {
+            enc.u8(FIELD_KIND, KIND_MOVE);
+            enc.path(FIELD_SRC, &params.src);
+            enc.path(FIELD_DST, &params.dst);
+            encode_error_policy(enc, params.error_policy);
+            encode_collision_policy(enc, params.collision_policy);
+            encode_symlink_policy(enc, params.symlink_policy);
+            enc.u8(FIELD_VERIFY, params.verify as u8);
+            encode_preserve(enc, params.preserve);
+        }
+        jobs::JobParams::Delete(params) => {
+            enc.u8(FIELD_KIND, KIND_DELETE);
+            enc.path(FIELD_SRC, &params.path);
+            enc.u8(
+                FIELD_DELETE_MODE,
+                match params.mode {
+                    jobs::DeleteMode::Trash => DELETE_MODE_TRASH,
+                    jobs::DeleteMode::Permanent => DELETE_MODE_PERMANENT,
+                },
+            );
+        }
+    }
+}
+
+fn decode_job(fields: &Fields) -> io::Result<jobs::Job> {
+    let params = match fields.u8(FIELD_KIND)? {
+        KIND_COPY => jobs::JobParams::Copy(jobs::CopyParams {
+            src: fields.path(FIELD_SRC)?,
+            dst: fields.path(FIELD_DST)?,
+            error_policy: decode_error_policy(fields)?,
+            collision_policy: decode_collision_policy(fields)?,
+            symlink_policy: decode_symlink_policy(fields)?,
+            verify: decode_verify(fields)?,
+            preserve: decode_preserve(fields)?,
+        }),
+        KIND_MOVE => jobs::JobParams::Move(jobs::MoveParams {
+            src: fields.path(FIELD_SRC)?,
+            dst: fields.path(FIELD_DST)?,
+            error_policy: decode_error_policy(fields)?,
+            collision_policy: decode_collision_policy(fields)?,
+            symlink_policy: decode_symlink_policy(fields)?,
+            verify: decode_verify(fields)?,
+            preserve: decode_preserve(fields)?,
+        }),
+        KIND_DELETE => jobs::JobParams::Delete(jobs::DeleteParams {
+            path: fields.path(FIELD_SRC)?,
+            mode: match fields.u8(FIELD_DELETE_MODE)? {
+                DELETE_MODE_TRASH => jobs::DeleteMode::Trash,
+                DELETE_MODE_PERMANENT => jobs::DeleteMode::Permanent,
+                mode => return Err(invalid_data(&format!("unknown delete mode {}", mode))),
+            },
+        }),
+        kind => return Err(invalid_data(&format!("unknown job kind {}", kind))),
+    };
+    Ok(jobs::Job {
+        id: fields.str(FIELD_JOB_ID)?,
+        params,
+    })
+}
+
+fn encode_record(record: &Record) -> Vec<u8> {
+    let enc = match record {
+        Record::Created(job) => {
+            let mut enc = Encoder::new(RECORD_CREATED);
+            encode_job(&mut enc, job);
+            enc
+        }
+        Record::Step { job_id, step } => {
+            let mut enc = match step {
+                Step::FileStarted { src, dst } => {
+                    let mut enc = Encoder::new(RECORD_FILE_STARTED);
+                    enc.path(FIELD_SRC, src);
+                    enc.path(FIELD_DST, dst);
+                    enc
+                }
+                Step::Checkpoint { src, dst, offset } => {
+                    let mut enc = Encoder::new(RECORD_CHECKPOINT);
+                    enc.path(FIELD_SRC, src);
+                    enc.path(FIELD_DST, dst);
+                    enc.u64(FIELD_OFFSET, *offset);
+                    enc
+                }
+                Step::FileDone { src, dst, bytes } => {
+                    let mut enc = Encoder::new(RECORD_FILE_DONE);
+                    enc.path(FIELD_SRC, src);
+                    enc.path(FIELD_DST, dst);
+                    enc.u64(FIELD_BYTES, *bytes);
+                    enc
+                }
+                Step::Skipped(error) => {
+                    let mut enc = Encoder::new(RECORD_SKIPPED);
+                    enc.path(FIELD_SRC, &error.path);
+                    enc.str(FIELD_REASON, &error.error);
+                    enc
+                }
+                Step::Kept(src) => {
+                    let mut enc = Encoder::new(RECORD_KEPT);
+                    enc.path(FIELD_SRC, src);
+                    enc
+                }
+            };
+            enc.str(FIELD_JOB_ID, job_id);
+            enc
+        }
+        Record::Finished { job_id, outcome } => {
+            let mut enc = Encoder::new(RECORD_FINISHED);
+            enc.str(FIELD_JOB_ID, job_id);
+            match outcome {
+                jobs::Outcome::Completed => enc.u8(FIELD_OUTCOME, OUTCOME_COMPLETED),
+                jobs::Outcome::Failed(reason) => {
+                    enc.u8(FIELD_OUTCOME, OUTCOME_FAILED);
+                    enc.str(FIELD_REASON, reason);
+                }
+                jobs::Outcome::Cancelled => enc.u8(FIELD_OUTCOME, OUTCOME_CANCELLED),
+            }
+            enc
+        }
+    };
+    enc.buf
+}
+
+// Ok(None) for record types this version doesn't know about.
+fn decode_record(payload: &[u8]) -> io::Result<Option<Record>> {
+    let fields = Fields::parse(payload)?;
+    let record = match fields.tag {
+        RECORD_CREATED => Record::Created(decode_job(&fields)?),
+        RECORD_FILE_STARTED => Record::Step {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            step: Step::FileStarted {
+                src: fields.path(FIELD_SRC)?,
+                dst: fields.path(FIELD_DST)?,
+            },
+        },
+        RECORD_CHECKPOINT => Record::Step {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            step: Step::Checkpoint {
+                src: fields.path(FIELD_SRC)?,
+                dst: fields.path(FIELD_DST)?,
+                offset: fields.u64(FIELD_OFFSET)?,
+            },
+        },
+        RECORD_FILE_DONE => Record::Step {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            step: Step::FileDone {
+                src: fields.path(FIELD_SRC)?,
+                dst: fields.path(FIELD_DST)?,
+                bytes: fields.u64(FIELD_BYTES)?,
+            },
+        },
+        RECORD_SKIPPED => Record::Step {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            step: Step::Skipped(jobs::FileError {
+                path: fields.path(FIELD_SRC)?,
+                error: fields.str(FIELD_REASON)?,
+            }),
+        },
+        RECORD_KEPT => Record::Step {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            step: Step::Kept(fields.path(FIELD_SRC)?),
+        },
+        RECORD_FINISHED => Record::Finished {
+            job_id: fields.str(FIELD_JOB_ID)?,
+            outcome: match fields.u8(FIELD_OUTCOME)? {
+                OUTCOME_COMPLETED => jobs::Outcome::Completed,
+                OUTCOME_FAILED => jobs::Outcome::Failed(fields.str(FIELD_REASON)?),
+                OUTCOME_CANCELLED => jobs::Outcome::Cancelled,
+                outcome => return Err(invalid_data(&format!("unknown outcome {}", outcome))),
+            },
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(record))
+}
+
+fn encode_frame(record: &Record) -> Vec<u8> {
+    frame(encode_record(record))
+}
+
+fn frame(payload: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend((payload.len() as u32).to_le_bytes());
+    frame.extend(fnv1a(&payload).to_le_bytes());
+    frame.extend(payload);
+    frame
+}
+
+// Returns the decoded records and the number of bytes they span. Decoding stops
+// at the first incomplete or corrupt frame, which can only be the torn tail.
+// Intact frames that don't decode, e.g. written by a newer version, are
+// reported and left out rather than keeping every other job from loading.
+fn decode_frames(buf: &[u8]) -> (Vec<Record>, usize) {
+    let mut records = vec![];
+    let mut pos = 0;
+    while buf.len() - pos >= FRAME_HEADER_LEN {
+        let header = &buf[pos..pos + FRAME_HEADER_LEN];
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let sum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let start = pos + FRAME_HEADER_LEN;
+        if buf.len() - start < len {
+            break;
+        }
+        let payload = &buf[start..start + len];
+        if fnv1a(payload) != sum {
+            break;
+        }
+        match decode_record(payload) {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => {}
+            Err(err) => eprintln!(
+                "WAL: skipping undecodable record at byte {} err={}",
+                pos, err
+            ),
+        }
+        pos = start + len;
+    }
+    (records, pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_wal_path() -> path::PathBuf {
+        env::temp_dir()
+            .join(format!("files_please-wal-{}", uuidv7::create()))
+            .join("jobs.wal")
+    }
+
+    fn copy_job() -> jobs::Job {
+        jobs::Job::new(jobs::JobParams::Copy(jobs::CopyParams {
+            src: path::PathBuf::from("/tmp/src/\u{e9}t\u{e9}\nwith newline"),
+            dst: path::PathBuf::from("/tmp/dst"),
+            error_policy: jobs::ErrorPolicy::Retry { attempts: 5 },
+            collision_policy: jobs::CollisionPolicy::Rename,
+            symlink_policy: jobs::SymlinkPolicy::DereferenceOutside,
+            verify: true,
+            preserve: jobs::Preserve {
+                xattrs: false,
+                ..jobs::Preserve::archive()
+            },
+        }))
+    }
+
+    #[test]
+    fn records_survive_reopen() {
+        let wal_path = temp_wal_path();
+        let job = copy_job();
+        let records = vec![
+            Record::Created(job.clone()),
+            Record::Step {
+                job_id: job.id.clone(),
+                step: Step::Checkpoint {
+                    src: path::PathBuf::from("/tmp/src/a"),
+                    dst: path::PathBuf::from("/tmp/dst/a"),
+                    offset: 1 << 40,
+                },
+            },
+            Record::Step {
+                job_id: job.id.clone(),
+                step: Step::Skipped(jobs::FileError {
+                    path: path::PathBuf::from("/tmp/src/b"),
+                    error: String::from("Permission denied"),
+                }),
+            },
+            Record::Step {
+                job_id: job.id.clone(),
+                step: Step::Kept(path::PathBuf::from("/tmp/src/c")),
+            },
+            Record::Finished {
+                job_id: job.id.clone(),
+                outcome: jobs::Outcome::Failed(String::from("disk full")),
+            },
+        ];
+
+        {
+            let (mut wal, replayed) = Wal::open(&wal_path).unwrap();
+            assert!(replayed.is_empty());
+            for record in records.iter() {
+                wal.append(record).unwrap();
+            }
+        }
+
+        let (_, replayed) = Wal::open(&wal_path).unwrap();
+        assert_eq!(replayed, records);
+
+        let _ = fs::remove_dir_all(wal_path.parent().unwrap());
+    }
+
+    #[test]
+    fn torn_tail_is_discarded() {
+        let wal_path = temp_wal_path();
+        let first = Record::Created(copy_job());
+        let second = Record::Created(copy_job());
+
+        {
+            let (mut wal, _) = Wal::open(&wal_path).unwrap();
+            wal.append(&first).unwrap();
+            let frame = encode_frame(&second);
+            wal.file.write_all(&frame[..frame.len() - 3]).unwrap();
+        }
+
+        {
+            let (mut wal, replayed) = Wal::open(&wal_path).unwrap();
+            assert_eq!(replayed, vec![first.clone()]);
+            wal.append(&second).unwrap();
+        }
+
+        let (_, replayed) = Wal::open(&wal_path).unwrap();
+        assert_eq!(replayed, vec![first, second]);
+
+        let _ = fs::remove_dir_all(wal_path.parent().unwrap());
+    }
+
+    #[test]
+    fn undecodable_records_are_skipped() {
+        let wal_path = temp_wal_path();
+        let first = Record::Created(copy_job());
+        let last = Record::Created(copy_job());
+
+        {
+            let (mut wal, _) = Wal::open(&wal_path).unwrap();
+            wal.append(&first).unwrap();
+            let mut enc = Encoder::new(RECORD_FINISHED);
+            enc.str(FIELD_JOB_ID, "job");
+            enc.u8(FIELD_OUTCOME, u8::MAX);
+            wal.file.write_all(&frame(enc.buf)).unwrap();
+            wal.append(&last).unwrap();
+        }
+
+        let (_, replayed) = Wal::open(&wal_path).unwrap();
+        assert_eq!(replayed, vec![first, last]);
+
+        let _ = fs::remove_dir_all(wal_path.parent().unwrap());
+    }
+
+    #[test]
+    fn in_flight_jobs_resume_from_last_checkpoint() {
+        let finished = copy_job();
+        let interrupted = copy_job();
+        let step = |job: &jobs::Job, step: Step| Record::Step {
+            job_id: job.id.clone(),
+            step,
+        };
+        let file = |name: &str| path::PathBuf::from(name);
+
+        let records = vec![
+            Record::Created(finished.clone()),
+            Record::Created(interrupted.clone()),
+            step(
+                &interrupted,
+                Step::FileDone {
+                    src: file("a"),
+                    dst: file("b"),
+                    bytes: 3,
+                },
+            ),
+            step(
+                &interrupted,
+                Step::Checkpoint {
+                    src: file("c"),
+                    dst: file("d"),
+                    offset: 4096,
+                },
+            ),
+            Record::Finished {
+                job_id: finished.id.clone(),
+                outcome: jobs::Outcome::Completed,
+            },
+        ];
+
+        let pending = in_flight(&records);
+        assert_eq!(pending.len(), 1);
+        let (job, resume) = &pending[0];
+        assert_eq!(job, &interrupted);
+        assert!(resume.done.contains(&file("a")));
+        assert_eq!(
+            resume.checkpoint,
+            Some(jobs::Checkpoint {
+                src: file("c"),
+                dst: file("d"),
+                offset: 4096,
+            })
+        );
+
+        let wal_path = temp_wal_path();
+        {
+            let (mut wal, _) = Wal::open(&wal_path).unwrap();
+            for record in records.iter() {
+                wal.append(record).unwrap();
+            }
+            let keep: Vec<Record> = records
+                .iter()
+                .filter(|r| r.job_id() == interrupted.id)
+                .cloned()
+                .collect();
+            wal.compact(&keep).unwrap();
+            wal.append(&records[4]).unwrap();
+        }
+        let (_, replayed) = Wal::open(&wal_path).unwrap();
+        assert_eq!(replayed.len(), 4);
+        assert_eq!(in_flight(&replayed).len(), 1);
+
+        let _ = fs::remove_dir_all(wal_path.parent().unwrap());
+    }
+}
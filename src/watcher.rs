@@ -0,0 +1,197 @@
+use std::collections;
+use std::ffi;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path;
+use std::ptr;
+
+// Changes to the entries themselves: created, deleted, renamed, written or
+// chmod'ed. Plain writes are left out as they come by the thousand; the
+// close after them is enough.
+const ENTRY_EVENTS: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB;
+
+const DIR_EVENTS: u32 = libc::IN_DELETE_SELF | libc::IN_MOVE_SELF;
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    // Something happened to the entry called `name`; whatever is there now
+    // is what counts.
+    Entry {
+        dir: path::PathBuf,
+        name: path::PathBuf,
+    },
+    // The watched directory itself was deleted or moved away.
+    DirGone {
+        dir: path::PathBuf,
+    },
+    // The kernel dropped events, anything watched may be out of date.
+    Overflow,
+}
+
+// Watches directories with inotify. Reading never blocks, so it can be polled
+// from the UI loop.
+pub struct Watcher {
+    fd: OwnedFd,
+    // Watch descriptors and the directories they are for.
+    watches: collections::HashMap<i32, path::PathBuf>,
+    // Directories that couldn't be watched, not tried again while they are
+    // asked for.
+    failed: collections::HashSet<path::PathBuf>,
+}
+
+impl Watcher {
+    pub fn new() -> io::Result<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Watcher {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: collections::HashMap::new(),
+            failed: collections::HashSet::new(),
+        })
+    }
+
+    // Watches exactly `dirs` from now on.
+    pub fn watch_only(&mut self, dirs: &[&path::Path]) {
+        let stale: Vec<i32> = self
+            .watches
+            .iter()
+            .filter(|(_, dir)| !dirs.contains(&dir.as_path()))
+            .map(|(wd, _)| *wd)
+            .collect();
+        for wd in stale {
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+            self.watches.remove(&wd);
+        }
+        self.failed.retain(|dir| dirs.contains(&dir.as_path()));
+        for dir in dirs {
+            if self.failed.contains(*dir) || self.watches.values().any(|watched| watched == dir) {
+                continue;
+            }
+            if let Err(err) = self.add(dir) {
+                eprintln!("Watch {} err={}", dir.display(), err);
+                self.failed.insert(dir.to_path_buf());
+            }
+        }
+    }
+
+    fn add(&mut self, dir: &path::Path) -> io::Result<()> {
+        let c_dir = ffi::CString::new(dir.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let wd = unsafe {
+            libc::inotify_add_watch(
+                self.fd.as_raw_fd(),
+                c_dir.as_ptr(),
+                ENTRY_EVENTS | DIR_EVENTS | libc::IN_ONLYDIR,
+            )
+        };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.watches.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    // What happened since the last call, oldest first.
+    pub fn changes(&mut self) -> Vec<Change> {
+        let mut changes = vec![];
+        // Room for plenty of events with names up to NAME_MAX, aligned for
+        // the event headers.
+        let mut buf = vec![0u64; 8192];
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len() * mem::size_of::<u64>(),
+                )
+            };
+            if n <= 0 {
+                // EAGAIN once everything has been read.
+                return changes;
+            }
+            let bytes =
+                unsafe { std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), n as usize) };
+            let mut offset = 0;
+            while offset + mem::size_of::<libc::inotify_event>() <= bytes.len() {
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(bytes[offset..].as_ptr().cast()) };
+                let name_start = offset + mem::size_of::<libc::inotify_event>();
+                let name_end = (name_start + event.len as usize).min(bytes.len());
+                offset = name_end;
+                let name = &bytes[name_start..name_end];
+                // The name is padded with NULs.
+                let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    changes.push(Change::Overflow);
+                    continue;
+                }
+                if event.mask & libc::IN_IGNORED != 0 {
+                    // The watch is gone, removed by us or with its directory.
+                    self.watches.remove(&event.wd);
+                    continue;
+                }
+                let Some(dir) = self.watches.get(&event.wd) else {
+                    continue;
+                };
+                if event.mask & DIR_EVENTS != 0 {
+                    changes.push(Change::DirGone { dir: dir.clone() });
+                } else if !name.is_empty() {
+                    changes.push(Change::Entry {
+                        dir: dir.clone(),
+                        name: path::PathBuf::from(ffi::OsStr::from_bytes(name)),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn reports_changed_names() {
+        let dir = env::temp_dir().join(format!("files_please-watch-{}", uuidv7::create()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch_only(&[&dir]);
+
+        fs::write(dir.join("new"), b"data").unwrap();
+        fs::rename(dir.join("new"), dir.join("renamed")).unwrap();
+        let changes = watcher.changes();
+        let entry = |name: &str| Change::Entry {
+            dir: dir.clone(),
+            name: path::PathBuf::from(name),
+        };
+        assert!(changes.contains(&entry("new")));
+        assert!(changes.contains(&entry("renamed")));
+
+        watcher.watch_only(&[]);
+        fs::write(dir.join("unwatched"), b"").unwrap();
+        assert!(!watcher.changes().contains(&entry("unwatched")));
+
+        let missing = dir.join("missing");
+        watcher.watch_only(&[&missing]);
+        assert!(watcher.failed.contains(&missing));
+        // Tried again once it has been out of view.
+        watcher.watch_only(&[]);
+        fs::create_dir(&missing).unwrap();
+        watcher.watch_only(&[&missing]);
+        assert!(watcher.watches.values().any(|watched| *watched == missing));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}